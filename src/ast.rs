@@ -1,5 +1,7 @@
+use num_bigint::BigInt;
 use crate::token::*;
 
+#[derive(Clone, Debug)]
 pub struct Identifier {
     pub tok: Token,
     pub value: String
@@ -8,7 +10,7 @@ pub struct Identifier {
 impl Identifier {
     pub fn new(tok: Token, val: &str) -> Identifier {
         Identifier {
-            tok: tok,
+            tok,
             value: val.to_string()
         }
     }
@@ -16,6 +18,10 @@ impl Identifier {
     pub fn token_literal(&self) -> &str {
         &self.tok.literal
     }
+
+    pub fn span(&self) -> Span {
+        self.tok.span
+    }
 }
 
 impl std::fmt::Display for Identifier {
@@ -24,18 +30,28 @@ impl std::fmt::Display for Identifier {
     }
 }
 
+#[derive(Clone, Debug)]
 pub enum Statement {
-    Let(Identifier, Expression),
-    Return(Expression),
-    Expression(Expression),
+    // the optional string is the text of the '///' doc comment right before the let
+    Let(Identifier, Expression, Option<String>, Span),
+    Return(Expression, Span),
+    Expression(Expression, Span),
 }
 
 impl Statement {
     pub fn token_literal(&self) -> String {
         match self {
-            Statement::Let(_, _) => String::from("let"),
-            Statement::Return(_)=> String::from("return"),
-            Statement::Expression(exp) => exp.to_string(),
+            Statement::Let(..) => String::from("let"),
+            Statement::Return(..)=> String::from("return"),
+            Statement::Expression(exp, _) => exp.to_string(),
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Statement::Let(_, _, _, span) => *span,
+            Statement::Return(_, span) => *span,
+            Statement::Expression(_, span) => *span,
         }
     }
 }
@@ -43,17 +59,13 @@ impl Statement {
 impl std::fmt::Display for Statement {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
         let str = match self {
-            Statement::Let(ident, exp) => {
+            Statement::Let(ident, exp, ..) => {
                 format!("{} {} = {};", self.token_literal(), ident, exp)
             },
-            Statement::Return(exp) => {
-                if exp.to_string() == "" {
-                    format!("{};", self.token_literal())
-                } else {
-                    format!("{} {};", self.token_literal(), exp)
-                }
+            Statement::Return(exp, _) => {
+                format!("{} {};", self.token_literal(), exp)
             },
-            Statement::Expression(exp) => exp.to_string()
+            Statement::Expression(exp, _) => exp.to_string()
         };
 
         write!(fmt, "{}", str)
@@ -61,26 +73,194 @@ impl std::fmt::Display for Statement {
 }
 
 
-//pub type Expression = String;
+#[derive(Clone, Debug)]
 pub enum Expression {
     Identifier(Identifier),
-    Literal(Literal),
+    Literal(Literal, Span),
+    Prefix {
+        operator: String,
+        right: Box<Expression>,
+        span: Span
+    },
+    Infix {
+        left: Box<Expression>,
+        operator: String,
+        right: Box<Expression>,
+        span: Span
+    },
+    If {
+        condition: Box<Expression>,
+        consequence: BlockStatement,
+        alternative: Option<BlockStatement>,
+        span: Span
+    },
+    FunctionLiteral {
+        params: Vec<Identifier>,
+        body: BlockStatement,
+        span: Span
+    },
+    Call {
+        function: Box<Expression>,
+        arguments: Vec<Expression>,
+        span: Span
+    },
+    ArrayLiteral {
+        elements: Vec<Expression>,
+        span: Span
+    },
+    Index {
+        left: Box<Expression>,
+        index: Box<Expression>,
+        span: Span
+    },
+    // pairs in source order
+    Hash {
+        pairs: Vec<(Expression, Expression)>,
+        span: Span
+    },
+}
+
+impl Expression {
+    pub fn span(&self) -> Span {
+        match self {
+            Expression::Identifier(ident) => ident.span(),
+            Expression::Literal(_, span)
+            | Expression::Prefix { span, .. }
+            | Expression::Infix { span, .. }
+            | Expression::If { span, .. }
+            | Expression::FunctionLiteral { span, .. }
+            | Expression::Call { span, .. }
+            | Expression::ArrayLiteral { span, .. }
+            | Expression::Index { span, .. }
+            | Expression::Hash { span, .. } => *span,
+        }
+    }
+
+    pub fn span_mut(&mut self) -> &mut Span {
+        match self {
+            Expression::Identifier(ident) => &mut ident.tok.span,
+            Expression::Literal(_, span)
+            | Expression::Prefix { span, .. }
+            | Expression::Infix { span, .. }
+            | Expression::If { span, .. }
+            | Expression::FunctionLiteral { span, .. }
+            | Expression::Call { span, .. }
+            | Expression::ArrayLiteral { span, .. }
+            | Expression::Index { span, .. }
+            | Expression::Hash { span, .. } => span,
+        }
+    }
 }
 
 impl std::fmt::Display for Expression {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
         let str = match self {
             Expression::Identifier(ident) => ident.to_string(),
-            Expression::Literal(l) => l.to_string(),
+            Expression::Literal(l, _) => l.to_string(),
+            Expression::Prefix { operator, right, .. } => format!("({}{})", operator, right),
+            Expression::Infix { left, operator, right, .. } => format!("({} {} {})", left, operator, right),
+            Expression::If { condition, consequence, alternative, .. } => {
+                match alternative {
+                    Some(alt) => format!("if ({}) {} else {}", condition, consequence, alt),
+                    None => format!("if ({}) {}", condition, consequence)
+                }
+            },
+            Expression::FunctionLiteral { params, body, .. } => {
+                let params = params.iter()
+                    .map(|p| p.to_string())
+                    .collect::<Vec<_>>();
+
+                format!("fn({}) {}", params.join(", "), body)
+            },
+            Expression::Call { function, arguments, .. } => {
+                let args = arguments.iter()
+                    .map(|a| a.to_string())
+                    .collect::<Vec<_>>();
+
+                format!("{}({})", function, args.join(", "))
+            },
+            Expression::ArrayLiteral { elements, .. } => {
+                let elements = elements.iter()
+                    .map(|e| e.to_string())
+                    .collect::<Vec<_>>();
+
+                format!("[{}]", elements.join(", "))
+            },
+            Expression::Index { left, index, .. } => format!("({}[{}])", left, index),
+            Expression::Hash { pairs, .. } => {
+                let pairs = pairs.iter()
+                    .map(|(key, value)| format!("{}: {}", key, value))
+                    .collect::<Vec<_>>();
+
+                format!("{{{}}}", pairs.join(", "))
+            },
         };
 
         write!(fmt, "{}", str)
     }
 }
 
+#[derive(Clone, Debug, Default)]
+pub struct BlockStatement {
+    pub statements: Vec<Statement>,
+    pub span: Span
+}
+
+impl BlockStatement {
+    pub fn new() -> BlockStatement {
+        BlockStatement {
+            statements: vec![],
+            span: Span::default()
+        }
+    }
+}
+
+impl std::fmt::Display for BlockStatement {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.statements.is_empty() {
+            return write!(fmt, "{{ }}");
+        }
+
+        let s = self.statements.iter()
+            .map(terminated)
+            .collect::<Vec<_>>();
+
+        write!(fmt, "{{ {} }}", s.join(" "))
+    }
+}
 
+// expression statements are terminated when a program or block is printed, so consecutive
+// ones don't merge into a call or an index when reparsed
+fn terminated(stmt: &Statement) -> String {
+    match stmt {
+        Statement::Expression(..) => format!("{};", stmt),
+        _ => stmt.to_string()
+    }
+}
+
+// the inverse of the lexer's escapes, so string literals display as valid source
+pub fn escape_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            ch if ch.is_control() => out.push_str(&format!("\\u{{{:x}}}", ch as u32)),
+            ch => out.push(ch),
+        }
+    }
+
+    out
+}
+
+#[derive(Clone, Debug)]
 pub enum Literal {
-    Int(i32),
+    Int(i64),
+    // an integer literal too large for an i64, only parsed when big integers are on
+    BigInt(BigInt),
+    Float(f64),
     String(String),
     Bool(bool)
 }
@@ -89,14 +269,17 @@ impl std::fmt::Display for Literal {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             Literal::Int(i) => write!(fmt, "{}", i),
-            Literal::String(s) => write!(fmt, "{}", s),
+            Literal::BigInt(i) => write!(fmt, "{}", i),
+            // debug formatting always keeps a '.' or an exponent, so it reads back as a float
+            Literal::Float(f) => write!(fmt, "{:?}", f),
+            Literal::String(s) => write!(fmt, "\"{}\"", escape_string(s)),
             Literal::Bool(b) => write!(fmt, "{}", b)   
         }
     }
 }
 
 
-
+#[derive(Clone, Debug, Default)]
 pub struct Program {
     pub statements: Vec<Statement>
 }
@@ -109,7 +292,7 @@ impl Program {
     }
 
     pub fn token_literal(&self) -> String {
-        if let Some(stmt) = self.statements.get(0) {
+        if let Some(stmt) = self.statements.first() {
             stmt.token_literal()
         } else {
             "".to_string()
@@ -117,11 +300,107 @@ impl Program {
     }
 }
 
+// the innermost node found by Program::node_at
+#[derive(Clone, Copy, Debug)]
+pub enum Node<'a> {
+    Statement(&'a Statement),
+    Expression(&'a Expression),
+    Identifier(&'a Identifier),
+}
+
+impl<'a> Node<'a> {
+    pub fn span(&self) -> Span {
+        match self {
+            Node::Statement(stmt) => stmt.span(),
+            Node::Expression(exp) => exp.span(),
+            Node::Identifier(ident) => ident.span(),
+        }
+    }
+}
+
+fn contains(span: Span, offset: usize) -> bool {
+    span.start <= offset && offset < span.end
+}
+
+fn identifier_at(ident: &Identifier, offset: usize) -> Option<Node<'_>> {
+    if contains(ident.span(), offset) {
+        Some(Node::Identifier(ident))
+    } else {
+        None
+    }
+}
+
+fn block_node_at(block: &BlockStatement, offset: usize) -> Option<Node<'_>> {
+    block.statements.iter().find_map(|stmt| statement_node_at(stmt, offset))
+}
+
+fn statement_node_at(stmt: &Statement, offset: usize) -> Option<Node<'_>> {
+    if !contains(stmt.span(), offset) {
+        return None;
+    }
+
+    let inner = match stmt {
+        Statement::Let(ident, exp, ..) => identifier_at(ident, offset).or_else(|| expression_node_at(exp, offset)),
+        Statement::Return(exp, _) | Statement::Expression(exp, _) => expression_node_at(exp, offset),
+    };
+
+    inner.or(Some(Node::Statement(stmt)))
+}
+
+fn expression_node_at(exp: &Expression, offset: usize) -> Option<Node<'_>> {
+    if !contains(exp.span(), offset) {
+        return None;
+    }
+
+    let inner = match exp {
+        Expression::Identifier(ident) => identifier_at(ident, offset),
+        Expression::Literal(..) => None,
+        Expression::Prefix { right, .. } => expression_node_at(right, offset),
+        Expression::Infix { left, right, .. } => {
+            expression_node_at(left, offset).or_else(|| expression_node_at(right, offset))
+        },
+        Expression::If { condition, consequence, alternative, .. } => {
+            expression_node_at(condition, offset)
+                .or_else(|| block_node_at(consequence, offset))
+                .or_else(|| alternative.as_ref().and_then(|alt| block_node_at(alt, offset)))
+        },
+        Expression::FunctionLiteral { params, body, .. } => {
+            params.iter()
+                .find_map(|p| identifier_at(p, offset))
+                .or_else(|| block_node_at(body, offset))
+        },
+        Expression::Call { function, arguments, .. } => {
+            expression_node_at(function, offset)
+                .or_else(|| arguments.iter().find_map(|arg| expression_node_at(arg, offset)))
+        },
+        Expression::ArrayLiteral { elements, .. } => {
+            elements.iter().find_map(|e| expression_node_at(e, offset))
+        },
+        Expression::Index { left, index, .. } => {
+            expression_node_at(left, offset).or_else(|| expression_node_at(index, offset))
+        },
+        Expression::Hash { pairs, .. } => {
+            pairs.iter().find_map(|(key, value)| {
+                expression_node_at(key, offset).or_else(|| expression_node_at(value, offset))
+            })
+        },
+    };
+
+    inner.or(Some(Node::Expression(exp)))
+}
+
+impl Program {
+    // innermost statement, expression or identifier covering the byte offset
+    pub fn node_at(&self, offset: usize) -> Option<Node<'_>> {
+        self.statements.iter().find_map(|stmt| statement_node_at(stmt, offset))
+    }
+}
+
 impl std::fmt::Display for Program {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
         let mut s = vec![];
         for stmt in &self.statements {
-            s.push(stmt.to_string());
+            s.push(terminated(stmt));
         }
 
         write!(fmt, "{}", s.join("\n"))
@@ -138,11 +417,13 @@ mod test {
             statements: vec![
                 Statement::Let(
                     Identifier::new(Token::new(TokenType::Ident, "my_var"), "my_var"),
-                    Expression::Identifier(Identifier::new(Token::new(TokenType::Ident, "another_var"), "another_var"))           
+                    Expression::Identifier(Identifier::new(Token::new(TokenType::Ident, "another_var"), "another_var")),
+                    None,
+                    Span::default()
                 ),
             ]
         };
 
-        assert_eq!(program.to_string(), "let my_var = another_var;", "program.to_string() is wrong. got='{}'", program.to_string());
+        assert_eq!(program.to_string(), "let my_var = another_var;", "program.to_string() is wrong. got='{}'", program);
     }
 }
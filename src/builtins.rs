@@ -0,0 +1,335 @@
+use std::num::IntErrorKind;
+use num_bigint::BigInt;
+use num_traits::FromPrimitive;
+use crate::evaluator::{Environment, ErrorKind, Object, OverflowPolicy};
+
+// builtins get the caller's environment for the state a program shares, like its output
+pub type BuiltinFunction = fn(Vec<Object>, &Environment) -> Object;
+
+pub struct Builtin {
+    pub name: &'static str,
+    // how it's called and what it does, shown by the repl's :builtins
+    pub usage: &'static str,
+    pub func: BuiltinFunction
+}
+
+// functions every program can call. they're only looked up when no binding has the
+// name, so a program can still shadow them with its own
+pub const BUILTINS: &[Builtin] = &[
+    Builtin { name: "puts", usage: "puts(x, ...): prints each argument on its own line", func: puts },
+    Builtin { name: "len", usage: "len(x): the length of a string, array or hash", func: len },
+    Builtin { name: "type", usage: "type(x): the type of x as a string", func: type_of },
+    Builtin { name: "str", usage: "str(x): x converted to a string", func: str },
+    Builtin { name: "int", usage: "int(x): a float, string or boolean converted to an integer", func: int },
+    Builtin { name: "bool", usage: "bool(x): whether x is truthy", func: bool },
+    Builtin { name: "first", usage: "first(a): the first element of an array", func: first },
+    Builtin { name: "last", usage: "last(a): the last element of an array", func: last },
+    Builtin { name: "rest", usage: "rest(a): an array without its first element", func: rest },
+    Builtin { name: "push", usage: "push(a, x): a new array with x added to the end", func: push },
+];
+
+pub fn lookup(name: &str) -> Option<Object> {
+    BUILTINS.iter()
+        .find(|builtin| builtin.name == name)
+        .map(|builtin| Object::Builtin(builtin.name, builtin.func))
+}
+
+// every builtin error starts with the builtin's name
+fn new_error(kind: ErrorKind, name: &str, msg: String) -> Object {
+    Object::Error(kind, format!("{}: {}", name, msg), None)
+}
+
+fn type_error(name: &str, expected: &str, got: &Object) -> Object {
+    new_error(ErrorKind::InvalidArgument, name, format!("expected {}, got {}", expected, got.type_name()))
+}
+
+fn check_arguments(name: &str, args: &[Object], expected: usize) -> Option<Object> {
+    if args.len() == expected {
+        None
+    } else {
+        Some(new_error(ErrorKind::WrongArgumentCount, name, format!("wrong number of arguments: expected {}, got {}", expected, args.len())))
+    }
+}
+
+// strings are printed without their quotes
+fn puts(args: Vec<Object>, env: &Environment) -> Object {
+    for arg in args {
+        if let Err(e) = env.print(&format!("{}\n", arg)) {
+            return new_error(ErrorKind::Output, "puts", format!("could not write output: {}", e));
+        }
+    }
+
+    Object::Null
+}
+
+fn len(args: Vec<Object>, _env: &Environment) -> Object {
+    if let Some(err) = check_arguments("len", &args, 1) {
+        return err;
+    }
+
+    match &args[0] {
+        // strings are counted in chars, not bytes
+        Object::String(s) => Object::Integer(s.chars().count() as i64),
+        Object::Array(elements) => Object::Integer(elements.len() as i64),
+        Object::Hash(hash) => Object::Integer(hash.len() as i64),
+        obj => type_error("len", "STRING, ARRAY or HASH", obj)
+    }
+}
+
+fn type_of(args: Vec<Object>, _env: &Environment) -> Object {
+    if let Some(err) = check_arguments("type", &args, 1) {
+        return err;
+    }
+
+    Object::String(args[0].type_name().to_string())
+}
+
+fn str(args: Vec<Object>, _env: &Environment) -> Object {
+    if let Some(err) = check_arguments("str", &args, 1) {
+        return err;
+    }
+
+    match &args[0] {
+        Object::String(s) => Object::String(s.clone()),
+        obj => Object::String(obj.to_string())
+    }
+}
+
+// floats are truncated towards zero, strings must hold a decimal integer. values too big
+// for an INTEGER become big integers under OverflowPolicy::Promote, like integer literals
+fn int(args: Vec<Object>, env: &Environment) -> Object {
+    if let Some(err) = check_arguments("int", &args, 1) {
+        return err;
+    }
+
+    let promote = env.overflow_policy() == OverflowPolicy::Promote;
+    match &args[0] {
+        Object::Integer(_) | Object::BigInt(_) => args[0].clone(),
+        Object::Float(f) if f.is_finite() && f.trunc() >= i64::MIN as f64 && f.trunc() < i64::MAX as f64 => Object::Integer(f.trunc() as i64),
+        Object::Float(f) => match BigInt::from_f64(f.trunc()) {
+            Some(i) if promote => Object::BigInt(i),
+            _ => new_error(ErrorKind::InvalidArgument, "int", format!("{:?} doesn't fit in an INTEGER", f))
+        },
+        Object::String(s) => match s.trim().parse::<i64>() {
+            Ok(i) => Object::Integer(i),
+            Err(e) if promote && matches!(e.kind(), IntErrorKind::PosOverflow | IntErrorKind::NegOverflow) => {
+                Object::BigInt(s.trim().parse().expect("an overflowing i64 is made of valid digits"))
+            },
+            Err(_) => new_error(ErrorKind::InvalidArgument, "int", format!("can't convert {} to INTEGER", args[0].inspect()))
+        },
+        Object::Boolean(b) => Object::Integer(i64::from(*b)),
+        obj => type_error("int", "INTEGER, FLOAT, STRING or BOOLEAN", obj)
+    }
+}
+
+// the same truthiness conditions use: only false and null are false
+fn bool(args: Vec<Object>, _env: &Environment) -> Object {
+    if let Some(err) = check_arguments("bool", &args, 1) {
+        return err;
+    }
+
+    Object::Boolean(args[0].is_truthy())
+}
+
+fn first(args: Vec<Object>, _env: &Environment) -> Object {
+    if let Some(err) = check_arguments("first", &args, 1) {
+        return err;
+    }
+
+    match &args[0] {
+        Object::Array(elements) => elements.first().cloned().unwrap_or(Object::Null),
+        obj => type_error("first", "ARRAY", obj)
+    }
+}
+
+fn last(args: Vec<Object>, _env: &Environment) -> Object {
+    if let Some(err) = check_arguments("last", &args, 1) {
+        return err;
+    }
+
+    match &args[0] {
+        Object::Array(elements) => elements.last().cloned().unwrap_or(Object::Null),
+        obj => type_error("last", "ARRAY", obj)
+    }
+}
+
+// everything but the first element, null for an empty array
+fn rest(args: Vec<Object>, _env: &Environment) -> Object {
+    if let Some(err) = check_arguments("rest", &args, 1) {
+        return err;
+    }
+
+    match &args[0] {
+        Object::Array(elements) if elements.is_empty() => Object::Null,
+        Object::Array(elements) => Object::Array(elements[1..].to_vec()),
+        obj => type_error("rest", "ARRAY", obj)
+    }
+}
+
+// arrays are values, push returns a new array and leaves the argument as it was
+fn push(args: Vec<Object>, _env: &Environment) -> Object {
+    if let Some(err) = check_arguments("push", &args, 2) {
+        return err;
+    }
+
+    match &args[0] {
+        Object::Array(elements) => {
+            let mut elements = elements.clone();
+            elements.push(args[1].clone());
+            Object::Array(elements)
+        },
+        obj => type_error("push", "ARRAY", obj)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::io::{self, Write};
+    use std::rc::Rc;
+    use crate::evaluator::{eval, Environment, OverflowPolicy};
+    use crate::parser::parse;
+
+    // the result and everything the program printed
+    fn test_eval_with_output(input: &str, overflow: OverflowPolicy, output: Rc<RefCell<dyn Write>>) -> String {
+        let program = match parse(input) {
+            Ok(program) => program,
+            Err(errors) => panic!("parser has {} errors: {:?}", errors.len(), errors)
+        };
+
+        eval(&program, &mut Environment::with_output(overflow, output)).to_string()
+    }
+
+    fn test_eval_with(input: &str, overflow: OverflowPolicy) -> String {
+        test_eval_with_output(input, overflow, Rc::new(RefCell::new(io::sink())))
+    }
+
+    fn test_eval(input: &str) -> String {
+        test_eval_with(input, OverflowPolicy::Checked)
+    }
+
+    struct BrokenPipe;
+
+    impl Write for BrokenPipe {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::from(io::ErrorKind::BrokenPipe))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_puts() {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let evaluated = test_eval_with_output("puts(\"hello\", 1, [\"a\"]); puts(); puts(true)", OverflowPolicy::Checked, output.clone());
+        assert_eq!(evaluated, "null", "puts should return null. got={}", evaluated);
+
+        let printed = String::from_utf8(output.borrow().clone()).unwrap_or_default();
+        assert_eq!(printed, "hello\n1\n[\"a\"]\ntrue\n", "wrong output. got={:?}", printed);
+
+        let evaluated = test_eval_with_output("puts(1)", OverflowPolicy::Checked, Rc::new(RefCell::new(BrokenPipe)));
+        assert!(evaluated.starts_with("ERROR: puts: could not write output: "), "write errors should be returned. got={}", evaluated);
+    }
+
+    #[test]
+    fn test_array_builtins() {
+        let tests = [
+            ("len(\"\")", "0"),
+            ("len(\"four\")", "4"),
+            ("len(\"héllo\")", "5"),
+            ("len([1, 2, 3])", "3"),
+            ("len([])", "0"),
+            ("len({1: 2, \"a\": 3})", "2"),
+            ("first([1, 2, 3])", "1"),
+            ("first([])", "null"),
+            ("last([1, 2, 3])", "3"),
+            ("last([])", "null"),
+            ("rest([1, 2, 3])", "[2, 3]"),
+            ("rest([1])", "[]"),
+            ("rest([])", "null"),
+            ("push([], 1)", "[1]"),
+            ("let a = [1]; let b = push(a, \"x\"); [a, b]", "[[1], [1, \"x\"]]"),
+            ("let len = fn(x) { 42 }; len([1])", "42"),
+        ];
+
+        for (input, expected) in tests.iter() {
+            let evaluated = test_eval(input);
+            assert_eq!(evaluated, *expected, "wrong result for {}. expected={}, got={}", input, expected, evaluated);
+        }
+    }
+
+    #[test]
+    fn test_conversion_builtins() {
+        let tests = [
+            ("type(1)", "INTEGER"),
+            ("type(1.5)", "FLOAT"),
+            ("type(\"a\")", "STRING"),
+            ("type({})", "HASH"),
+            ("type(fn(x) { x })", "FUNCTION"),
+            ("type(len)", "BUILTIN"),
+            ("type(if (false) { 1 })", "NULL"),
+            ("str(12)", "12"),
+            ("str(\"a\")", "a"),
+            ("str([1, \"a\"])", "[1, \"a\"]"),
+            ("str(2.0) + str(true)", "2.0true"),
+            ("int(42)", "42"),
+            ("int(-2.9)", "-2"),
+            ("int(\" -17 \")", "-17"),
+            ("int(true) + int(false)", "1"),
+            ("bool(0)", "true"),
+            ("bool(\"\")", "true"),
+            ("bool(false)", "false"),
+            ("bool(if (false) { 1 })", "false"),
+        ];
+
+        for (input, expected) in tests.iter() {
+            let evaluated = test_eval(input);
+            assert_eq!(evaluated, *expected, "wrong result for {}. expected={}, got={}", input, expected, evaluated);
+        }
+    }
+
+    #[test]
+    fn test_builtin_errors() {
+        let tests = [
+            ("len(1)", "ERROR: len: expected STRING, ARRAY or HASH, got INTEGER"),
+            ("len(\"one\", \"two\")", "ERROR: len: wrong number of arguments: expected 1, got 2"),
+            ("first(\"abc\")", "ERROR: first: expected ARRAY, got STRING"),
+            ("last(true)", "ERROR: last: expected ARRAY, got BOOLEAN"),
+            ("rest(1.5)", "ERROR: rest: expected ARRAY, got FLOAT"),
+            ("push([1])", "ERROR: push: wrong number of arguments: expected 2, got 1"),
+            ("push(1, 1)", "ERROR: push: expected ARRAY, got INTEGER"),
+            ("type()", "ERROR: type: wrong number of arguments: expected 1, got 0"),
+            ("str(1, 2)", "ERROR: str: wrong number of arguments: expected 1, got 2"),
+            ("int([1])", "ERROR: int: expected INTEGER, FLOAT, STRING or BOOLEAN, got ARRAY"),
+            ("int(\"12a\")", "ERROR: int: can't convert \"12a\" to INTEGER"),
+            ("int(\"99999999999999999999\")", "ERROR: int: can't convert \"99999999999999999999\" to INTEGER"),
+            ("int(1.0 / 0.0)", "ERROR: int: inf doesn't fit in an INTEGER"),
+            ("int(1e19)", "ERROR: int: 1e19 doesn't fit in an INTEGER"),
+            ("bool()", "ERROR: bool: wrong number of arguments: expected 1, got 0"),
+        ];
+
+        for (input, expected) in tests.iter() {
+            let evaluated = test_eval(input);
+            assert_eq!(evaluated, *expected, "wrong error for {}. expected={}, got={}", input, expected, evaluated);
+        }
+    }
+
+    #[test]
+    fn test_int_with_promote() {
+        let tests = [
+            ("int(\"99999999999999999999\")", "99999999999999999999"),
+            ("int(\" -99999999999999999999 \") + 1", "-99999999999999999998"),
+            ("int(\"1_0\")", "ERROR: int: can't convert \"1_0\" to INTEGER"),
+            ("int(1e19)", "10000000000000000000"),
+            ("int(\"12a\")", "ERROR: int: can't convert \"12a\" to INTEGER"),
+            ("int(1.0 / 0.0)", "ERROR: int: inf doesn't fit in an INTEGER"),
+        ];
+
+        for (input, expected) in tests.iter() {
+            let evaluated = test_eval_with(input, OverflowPolicy::Promote);
+            assert_eq!(evaluated, *expected, "wrong result for {}. expected={}, got={}", input, expected, evaluated);
+        }
+    }
+}
@@ -0,0 +1,726 @@
+use std::fmt;
+use std::ops::Range;
+use std::rc::Rc;
+use crate::token::{Token, TokenType};
+use crate::lexer::Lexer;
+use crate::parser::{Precedence, token_precedence};
+
+// a lossless concrete syntax tree. the green tree is immutable and position independent,
+// it only knows kinds, text and widths, so it can be shared and rebuilt cheaply. the red
+// tree is a cursor on top of it that adds absolute offsets and parent links.
+// concatenating the text of every token gives back the exact source.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeKind {
+    Program,
+    LetStatement,
+    ReturnStatement,
+    ExpressionStatement,
+    Block,
+    Name,
+    Literal,
+    Prefix,
+    Infix,
+    Paren,
+    If,
+    Function,
+    ParamList,
+    Call,
+    ArgList,
+    Array,
+    Index,
+    Hash,
+    // tokens the grammar didn't expect, kept so nothing is lost
+    Error,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct GreenToken {
+    pub kind: TokenType,
+    pub text: String
+}
+
+#[derive(Debug, PartialEq)]
+pub struct GreenNode {
+    pub kind: NodeKind,
+    pub width: usize,
+    pub children: Vec<GreenElement>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GreenElement {
+    Node(Rc<GreenNode>),
+    Token(Rc<GreenToken>),
+}
+
+impl GreenElement {
+    pub fn width(&self) -> usize {
+        match self {
+            GreenElement::Node(node) => node.width,
+            GreenElement::Token(token) => token.text.len(),
+        }
+    }
+}
+
+impl GreenNode {
+    pub fn new(kind: NodeKind, children: Vec<GreenElement>) -> GreenNode {
+        let width = children.iter().map(|c| c.width()).sum();
+        GreenNode {
+            kind,
+            width,
+            children
+        }
+    }
+}
+
+impl fmt::Display for GreenNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for child in &self.children {
+            match child {
+                GreenElement::Node(node) => write!(f, "{}", node)?,
+                GreenElement::Token(token) => write!(f, "{}", token.text)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// builds green nodes bottom up, children are collected until their node is finished
+#[derive(Default)]
+struct GreenBuilder {
+    parents: Vec<(NodeKind, usize)>,
+    children: Vec<GreenElement>
+}
+
+impl GreenBuilder {
+    fn start_node(&mut self, kind: NodeKind) {
+        self.parents.push((kind, self.children.len()));
+    }
+
+    fn checkpoint(&self) -> usize {
+        self.children.len()
+    }
+
+    // starts a node that also wraps everything added since the checkpoint,
+    // that's how infix and call expressions adopt their left operand
+    fn start_node_at(&mut self, checkpoint: usize, kind: NodeKind) {
+        self.parents.push((kind, checkpoint));
+    }
+
+    fn token(&mut self, kind: TokenType, text: &str) {
+        self.children.push(GreenElement::Token(Rc::new(GreenToken {
+            kind,
+            text: text.to_string()
+        })));
+    }
+
+    fn finish_node(&mut self) {
+        let (kind, first) = self.parents.pop().expect("finish_node called without a node");
+        let children = self.children.split_off(first);
+        self.children.push(GreenElement::Node(Rc::new(GreenNode::new(kind, children))));
+    }
+
+    fn finish(mut self) -> Rc<GreenNode> {
+        match (self.parents.is_empty(), self.children.pop()) {
+            (true, Some(GreenElement::Node(node))) if self.children.is_empty() => node,
+            _ => panic!("the builder must end with a single root node")
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SyntaxNode(Rc<NodeData>);
+
+struct NodeData {
+    green: Rc<GreenNode>,
+    offset: usize,
+    parent: Option<SyntaxNode>
+}
+
+#[derive(Clone)]
+pub struct SyntaxToken {
+    green: Rc<GreenToken>,
+    offset: usize,
+    parent: SyntaxNode
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+impl SyntaxNode {
+    pub fn new_root(green: Rc<GreenNode>) -> SyntaxNode {
+        SyntaxNode(Rc::new(NodeData {
+            green,
+            offset: 0,
+            parent: None
+        }))
+    }
+
+    pub fn kind(&self) -> NodeKind {
+        self.0.green.kind
+    }
+
+    pub fn green(&self) -> &Rc<GreenNode> {
+        &self.0.green
+    }
+
+    // byte range in the source
+    pub fn range(&self) -> Range<usize> {
+        self.0.offset..self.0.offset + self.0.green.width
+    }
+
+    pub fn parent(&self) -> Option<SyntaxNode> {
+        self.0.parent.clone()
+    }
+
+    pub fn children_with_tokens(&self) -> Vec<SyntaxElement> {
+        let mut offset = self.0.offset;
+        self.0.green.children.iter()
+            .map(|child| {
+                let element = match child {
+                    GreenElement::Node(green) => SyntaxElement::Node(SyntaxNode(Rc::new(NodeData {
+                        green: green.clone(),
+                        offset,
+                        parent: Some(self.clone())
+                    }))),
+                    GreenElement::Token(green) => SyntaxElement::Token(SyntaxToken {
+                        green: green.clone(),
+                        offset,
+                        parent: self.clone()
+                    }),
+                };
+                offset += child.width();
+                element
+            })
+            .collect()
+    }
+
+    pub fn children(&self) -> Vec<SyntaxNode> {
+        self.children_with_tokens().into_iter()
+            .filter_map(|element| match element {
+                SyntaxElement::Node(node) => Some(node),
+                SyntaxElement::Token(_) => None
+            })
+            .collect()
+    }
+
+    // this node and every node below it, in source order
+    pub fn descendants(&self) -> Vec<SyntaxNode> {
+        let mut nodes = vec![self.clone()];
+        for child in self.children() {
+            nodes.extend(child.descendants());
+        }
+
+        nodes
+    }
+
+    // every token below this node, trivia included, in source order
+    pub fn tokens(&self) -> Vec<SyntaxToken> {
+        let mut tokens = vec![];
+        for element in self.children_with_tokens() {
+            match element {
+                SyntaxElement::Node(node) => tokens.extend(node.tokens()),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+
+        tokens
+    }
+
+    pub fn text(&self) -> String {
+        self.0.green.to_string()
+    }
+
+    // an indented tree with kinds, ranges and token text, handy for tests and debugging
+    pub fn dump(&self) -> String {
+        let mut out = String::new();
+        self.dump_into(&mut out, 0);
+        out
+    }
+
+    fn dump_into(&self, out: &mut String, depth: usize) {
+        out.push_str(&format!("{}{:?}\n", "  ".repeat(depth), self));
+        for element in self.children_with_tokens() {
+            match element {
+                SyntaxElement::Node(node) => node.dump_into(out, depth + 1),
+                SyntaxElement::Token(token) => out.push_str(&format!("{}{:?}\n", "  ".repeat(depth + 1), token)),
+            }
+        }
+    }
+}
+
+// two red nodes are the same if they point at the same green node in the same place
+impl PartialEq for SyntaxNode {
+    fn eq(&self, other: &SyntaxNode) -> bool {
+        Rc::ptr_eq(&self.0.green, &other.0.green) && self.0.offset == other.0.offset
+    }
+}
+
+impl fmt::Debug for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let range = self.range();
+        write!(f, "{:?}@{}..{}", self.kind(), range.start, range.end)
+    }
+}
+
+impl fmt::Display for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0.green)
+    }
+}
+
+impl SyntaxToken {
+    pub fn kind(&self) -> &TokenType {
+        &self.green.kind
+    }
+
+    pub fn text(&self) -> &str {
+        &self.green.text
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.offset..self.offset + self.green.text.len()
+    }
+
+    pub fn parent(&self) -> &SyntaxNode {
+        &self.parent
+    }
+
+    pub fn is_trivia(&self) -> bool {
+        self.green.kind.is_trivia()
+    }
+}
+
+impl PartialEq for SyntaxToken {
+    fn eq(&self, other: &SyntaxToken) -> bool {
+        Rc::ptr_eq(&self.green, &other.green) && self.offset == other.offset
+    }
+}
+
+impl fmt::Debug for SyntaxToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let range = self.range();
+        write!(f, "{:?}@{}..{} {:?}", self.kind(), range.start, range.end, self.text())
+    }
+}
+
+// builds the tree for any input, errors included. it never reports anything, diagnostics
+// come from parser::parse, this only has to keep every byte somewhere in the tree.
+// trivia before a node goes to its parent, so nodes start at their first real token
+pub fn parse(input: &str) -> SyntaxNode {
+    let mut lexer = Lexer::with_trivia(input);
+    let mut tokens = vec![];
+
+    loop {
+        let tok = lexer.next_token();
+        if tok.typ == TokenType::EOF {
+            // the lexer stops at a nul char, whatever comes after it is still source
+            if tok.span.start < input.len() {
+                let mut rest = Token::new(TokenType::Illegal, &input[tok.span.start..]);
+                rest.span.start = tok.span.start;
+                rest.span.end = input.len();
+                tokens.push(rest);
+            }
+            break;
+        }
+        tokens.push(tok);
+    }
+
+    let mut p = CstParser {
+        src: input,
+        tokens,
+        pos: 0,
+        builder: GreenBuilder::default()
+    };
+    p.parse_program();
+
+    SyntaxNode::new_root(p.builder.finish())
+}
+
+struct CstParser<'a> {
+    src: &'a str,
+    tokens: Vec<Token>,
+    pos: usize,
+    builder: GreenBuilder
+}
+
+impl<'a> CstParser<'a> {
+    // the next token that isn't trivia
+    fn current(&self) -> TokenType {
+        self.tokens[self.pos..].iter()
+            .find(|tok| !tok.typ.is_trivia())
+            .map(|tok| tok.typ.clone())
+            .unwrap_or(TokenType::EOF)
+    }
+
+    fn at(&self, typ: TokenType) -> bool {
+        self.current() == typ
+    }
+
+    fn push_token(&mut self) {
+        let tok = &self.tokens[self.pos];
+        self.builder.token(tok.typ.clone(), &self.src[tok.span.start..tok.span.end]);
+        self.pos += 1;
+    }
+
+    fn eat_trivia(&mut self) {
+        while self.pos < self.tokens.len() && self.tokens[self.pos].typ.is_trivia() {
+            self.push_token();
+        }
+    }
+
+    fn bump(&mut self) {
+        self.eat_trivia();
+        if self.pos < self.tokens.len() {
+            self.push_token();
+        }
+    }
+
+    fn expect(&mut self, typ: TokenType) -> bool {
+        if self.at(typ) {
+            self.bump();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn start_node(&mut self, kind: NodeKind) {
+        self.eat_trivia();
+        self.builder.start_node(kind);
+    }
+
+    fn finish_node(&mut self) {
+        self.builder.finish_node();
+    }
+
+    fn checkpoint(&mut self) -> usize {
+        self.eat_trivia();
+        self.builder.checkpoint()
+    }
+
+    fn node(&mut self, kind: NodeKind) {
+        self.start_node(kind);
+        self.bump();
+        self.finish_node();
+    }
+
+    fn parse_program(&mut self) {
+        self.builder.start_node(NodeKind::Program);
+        while !self.at(TokenType::EOF) {
+            if self.at(TokenType::Rbrace) {
+                self.node(NodeKind::Error);
+            } else {
+                self.parse_statement();
+            }
+        }
+
+        self.eat_trivia();
+        self.finish_node();
+    }
+
+    fn parse_statement(&mut self) {
+        match self.current() {
+            TokenType::Let => {
+                self.start_node(NodeKind::LetStatement);
+                self.bump();
+                if self.at(TokenType::Ident) {
+                    self.node(NodeKind::Name);
+                }
+                self.expect(TokenType::Assign);
+                self.parse_expression(Precedence::Lowest);
+                self.expect(TokenType::Semicolon);
+                self.finish_node();
+            },
+            TokenType::Return => {
+                self.start_node(NodeKind::ReturnStatement);
+                self.bump();
+                self.parse_expression(Precedence::Lowest);
+                self.expect(TokenType::Semicolon);
+                self.finish_node();
+            },
+            _ => {
+                self.start_node(NodeKind::ExpressionStatement);
+                self.parse_expression(Precedence::Lowest);
+                self.expect(TokenType::Semicolon);
+                self.finish_node();
+            }
+        }
+    }
+
+    fn parse_expression(&mut self, precedence: Precedence) {
+        let checkpoint = self.checkpoint();
+        if !self.parse_prefix() {
+            return;
+        }
+
+        while precedence < token_precedence(&self.current()) {
+            if self.at(TokenType::Lparen) {
+                self.builder.start_node_at(checkpoint, NodeKind::Call);
+                self.start_node(NodeKind::ArgList);
+                self.parse_expression_list(TokenType::Rparen);
+                self.finish_node();
+            } else if self.at(TokenType::Lbracket) {
+                self.builder.start_node_at(checkpoint, NodeKind::Index);
+                self.bump();
+                self.parse_expression(Precedence::Lowest);
+                self.expect(TokenType::Rbracket);
+            } else {
+                let operator_precedence = token_precedence(&self.current());
+                self.builder.start_node_at(checkpoint, NodeKind::Infix);
+                self.bump();
+                self.parse_expression(operator_precedence);
+            }
+            self.finish_node();
+        }
+    }
+
+    // false when there was no expression, tokens that can't start one become an error node
+    fn parse_prefix(&mut self) -> bool {
+        match self.current() {
+            TokenType::Ident => self.node(NodeKind::Name),
+            TokenType::Int | TokenType::Float | TokenType::String | TokenType::True | TokenType::False
+                | TokenType::UnterminatedString | TokenType::InvalidEscape => self.node(NodeKind::Literal),
+            TokenType::Bang | TokenType::Minus => {
+                self.start_node(NodeKind::Prefix);
+                self.bump();
+                self.parse_expression(Precedence::Prefix);
+                self.finish_node();
+            },
+            TokenType::Lparen => {
+                self.start_node(NodeKind::Paren);
+                self.bump();
+                self.parse_expression(Precedence::Lowest);
+                self.expect(TokenType::Rparen);
+                self.finish_node();
+            },
+            TokenType::If => self.parse_if_expression(),
+            TokenType::Function => self.parse_function_literal(),
+            TokenType::Lbracket => {
+                self.start_node(NodeKind::Array);
+                self.parse_expression_list(TokenType::Rbracket);
+                self.finish_node();
+            },
+            TokenType::Lbrace => self.parse_hash_literal(),
+            TokenType::EOF | TokenType::Semicolon | TokenType::Rbrace => return false,
+            _ => {
+                self.node(NodeKind::Error);
+                return false;
+            }
+        }
+
+        true
+    }
+
+    fn parse_if_expression(&mut self) {
+        self.start_node(NodeKind::If);
+        self.bump();
+        self.expect(TokenType::Lparen);
+        self.parse_expression(Precedence::Lowest);
+        self.expect(TokenType::Rparen);
+        self.parse_block_statement();
+
+        if self.expect(TokenType::Else) {
+            self.parse_block_statement();
+        }
+        self.finish_node();
+    }
+
+    fn parse_block_statement(&mut self) {
+        if !self.at(TokenType::Lbrace) {
+            return;
+        }
+
+        self.start_node(NodeKind::Block);
+        self.bump();
+        while !self.at(TokenType::Rbrace) && !self.at(TokenType::EOF) {
+            self.parse_statement();
+        }
+        self.expect(TokenType::Rbrace);
+        self.finish_node();
+    }
+
+    fn parse_function_literal(&mut self) {
+        self.start_node(NodeKind::Function);
+        self.bump();
+
+        if self.at(TokenType::Lparen) {
+            self.start_node(NodeKind::ParamList);
+            self.bump();
+            loop {
+                match self.current() {
+                    TokenType::Ident => self.node(NodeKind::Name),
+                    TokenType::Comma => self.bump(),
+                    _ => break
+                }
+            }
+            self.expect(TokenType::Rparen);
+            self.finish_node();
+        }
+
+        self.parse_block_statement();
+        self.finish_node();
+    }
+
+    fn parse_hash_literal(&mut self) {
+        self.start_node(NodeKind::Hash);
+        self.bump();
+
+        while !self.at(TokenType::Rbrace) && !self.at(TokenType::EOF) {
+            self.parse_expression(Precedence::Lowest);
+            self.expect(TokenType::Colon);
+            self.parse_expression(Precedence::Lowest);
+            if !self.expect(TokenType::Comma) {
+                break;
+            }
+        }
+        self.expect(TokenType::Rbrace);
+        self.finish_node();
+    }
+
+    // the opening token, comma separated expressions and the closing token
+    fn parse_expression_list(&mut self, end: TokenType) {
+        self.bump();
+
+        if !self.at(end.clone()) {
+            self.parse_expression(Precedence::Lowest);
+            while self.expect(TokenType::Comma) {
+                self.parse_expression(Precedence::Lowest);
+            }
+        }
+        self.expect(end);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let inputs = [
+            "",
+            "   \n\t",
+            "let x = 5;",
+            "let add = fn(a, b) {\n  // sum\n  a + b\n};\n\nadd(1, /* two */ 2);\n",
+            "/// docs\nlet f = fn(x) { if (x < 1) { return 0; } else { f(x - 1) } };\n",
+            "let s = \"a \\\"quoted\\\" \\u{1F600}\";",
+            "let naïve = \"héllo\"; // ünïcode",
+            "!-5 * (1 + 2) == -(3)",
+            "let a = [1, [2, 3], []]; a[1][0] + first(a[ 1 ]);",
+            "let h = {\"a\": 1, 2: {}, true: [3],}; h[\"a\"]",
+            // broken input still keeps every byte
+            "let = 5; let x 1; }",
+            "fn(1, ) { @ } ) ; ;",
+            "if (x { let",
+            "[1, 2; a[ ; ]",
+            "{1 2, : ; {",
+            "let s = \"never closed",
+            "let s = \"bad \\q escape\";",
+            "x /* never closed",
+            "a\u{0}b c",
+        ];
+
+        for input in inputs.iter() {
+            let tree = parse(input);
+            assert_eq!(tree.text(), *input, "round trip failed. got={:?}\n{}", tree.text(), tree.dump());
+            assert_eq!(tree.range(), 0..input.len(), "wrong root range for {:?}. got={:?}", input, tree.range());
+
+            let text = tree.tokens().iter().map(|t| t.text().to_string()).collect::<String>();
+            assert_eq!(text, *input, "tokens don't cover the input. got={:?}", text);
+        }
+    }
+
+    #[test]
+    fn test_tree_shape() {
+        let input = "let x = 1 + 2 * f(y); // c\n";
+        let expected = r#"Program@0..27
+  LetStatement@0..21
+    Let@0..3 "let"
+    Whitespace@3..4 " "
+    Name@4..5
+      Ident@4..5 "x"
+    Whitespace@5..6 " "
+    Assign@6..7 "="
+    Whitespace@7..8 " "
+    Infix@8..20
+      Literal@8..9
+        Int@8..9 "1"
+      Whitespace@9..10 " "
+      Plus@10..11 "+"
+      Whitespace@11..12 " "
+      Infix@12..20
+        Literal@12..13
+          Int@12..13 "2"
+        Whitespace@13..14 " "
+        Asterisk@14..15 "*"
+        Whitespace@15..16 " "
+        Call@16..20
+          Name@16..17
+            Ident@16..17 "f"
+          ArgList@17..20
+            Lparen@17..18 "("
+            Name@18..19
+              Ident@18..19 "y"
+            Rparen@19..20 ")"
+    Semicolon@20..21 ";"
+  Whitespace@21..22 " "
+  Comment@22..26 "// c"
+  Whitespace@26..27 "\n"
+"#;
+
+        let tree = parse(input);
+        assert_eq!(tree.dump(), expected, "wrong tree. got=\n{}", tree.dump());
+    }
+
+    #[test]
+    fn test_red_tree_navigation() {
+        let input = "if (a) {\n  b(1, 2)\n} else { c }";
+        let tree = parse(input);
+
+        let call = tree.descendants().into_iter()
+            .find(|node| node.kind() == NodeKind::Call)
+            .expect("no call node");
+        assert_eq!(&input[call.range()], "b(1, 2)", "wrong call range. got={:?}", call.range());
+        assert_eq!(call.text(), "b(1, 2)", "wrong call text. got={:?}", call.text());
+
+        let ancestors = std::iter::successors(call.parent(), |node| node.parent())
+            .map(|node| node.kind())
+            .collect::<Vec<_>>();
+        let expected = vec![NodeKind::ExpressionStatement, NodeKind::Block, NodeKind::If, NodeKind::ExpressionStatement, NodeKind::Program];
+        assert_eq!(ancestors, expected, "wrong ancestors. got={:?}", ancestors);
+
+        let args = call.children()[1].children().iter().map(|node| node.text()).collect::<Vec<_>>();
+        assert_eq!(args, vec!["1", "2"], "wrong arguments. got={:?}", args);
+
+        for token in tree.tokens() {
+            assert_eq!(&input[token.range()], token.text(), "token text doesn't match its range. got={:?}", token);
+            assert!(token.parent().range().start <= token.range().start, "token outside its parent. got={:?}", token);
+        }
+    }
+
+    #[test]
+    fn test_errors_are_kept_in_error_nodes() {
+        let tree = parse("let x = @; }");
+        let errors = tree.descendants().into_iter()
+            .filter(|node| node.kind() == NodeKind::Error)
+            .map(|node| node.text())
+            .collect::<Vec<_>>();
+
+        assert_eq!(errors, vec!["@", "}"], "wrong error nodes. got={:?}\n{}", errors, tree.dump());
+    }
+
+    #[test]
+    fn test_green_nodes_are_shared() {
+        let tree = parse("let x = 1;");
+        let a = tree.children()[0].clone();
+        let b = tree.children()[0].clone();
+
+        assert_eq!(a, b, "same node should compare equal");
+        assert!(Rc::ptr_eq(a.green(), b.green()), "red nodes should share their green node");
+    }
+}
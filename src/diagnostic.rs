@@ -0,0 +1,374 @@
+use crate::token::Span;
+use crate::parser::{ParseError, radix_name};
+use crate::evaluator::{ErrorKind, MAX_CALL_DEPTH};
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const BOLD_RED: &str = "\x1b[1;31m";
+const BOLD_BLUE: &str = "\x1b[1;34m";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(fmt, "error"),
+            Severity::Warning => write!(fmt, "warning"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NoteKind {
+    Help,
+    Note,
+}
+
+impl std::fmt::Display for NoteKind {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            NoteKind::Help => write!(fmt, "help"),
+            NoteKind::Note => write!(fmt, "note"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Note {
+    pub kind: NoteKind,
+    pub message: String
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub span: Option<Span>,
+    pub notes: Vec<Note>
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: &str) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            code,
+            message: message.to_string(),
+            span: None,
+            notes: vec![]
+        }
+    }
+
+    pub fn with_span(mut self, span: Span) -> Diagnostic {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_help(mut self, help: &str) -> Diagnostic {
+        self.notes.push(Note {
+            kind: NoteKind::Help,
+            message: help.to_string()
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: &str) -> Diagnostic {
+        self.notes.push(Note {
+            kind: NoteKind::Note,
+            message: note.to_string()
+        });
+        self
+    }
+
+    pub fn from_parse_error(err: &ParseError) -> Diagnostic {
+        let diagnostic = Diagnostic::error(err.code(), &err.to_string()).with_span(err.span());
+
+        match err {
+            ParseError::NoPrefixParseFn { .. } => diagnostic.with_help("expected an expression here"),
+            ParseError::InvalidIntegerLiteral { .. } => diagnostic.with_help("integer literals must fit in 64 bits"),
+            ParseError::UnterminatedBlock { .. } => diagnostic.with_help("add a '}' to close this block"),
+            ParseError::UnterminatedString { .. } => diagnostic.with_help("add a closing '\"' to end the string"),
+            ParseError::InvalidEscape { .. } => diagnostic.with_help("valid escapes are \\n, \\t, \\\\, \\\" and \\u{...}"),
+            ParseError::UnterminatedComment { .. } => diagnostic.with_help("block comments nest, every '/*' needs its own '*/'"),
+            ParseError::InvalidFloatLiteral { .. } => diagnostic.with_help("the largest float literal is about 1.8e308"),
+            ParseError::MissingDigits { .. } => diagnostic.with_help("'0x', '0o' and '0b' must be followed by at least one digit"),
+            ParseError::InvalidDigit { radix, .. } => {
+                let digits = match radix {
+                    2 => "0 and 1",
+                    8 => "0 to 7",
+                    _ => "0 to 9 and a to f"
+                };
+                diagnostic.with_help(&format!("{} literals only use the digits {}", radix_name(*radix), digits))
+            },
+            _ => diagnostic
+        }
+    }
+
+    pub fn runtime_error(kind: ErrorKind, message: &str, span: Option<Span>) -> Diagnostic {
+        let mut diagnostic = Diagnostic::error(kind.code(), message);
+        if let Some(span) = span {
+            diagnostic = diagnostic.with_span(span);
+        }
+
+        match kind {
+            ErrorKind::StackOverflow => diagnostic
+                .with_note(&format!("calls can only nest {} deep", MAX_CALL_DEPTH))
+                .with_help("check for recursion that never reaches its base case"),
+            _ => diagnostic
+        }
+    }
+
+    // failures of the monkey command itself rather than of the program it was given
+    pub fn read_error(path: &str, err: &std::io::Error) -> Diagnostic {
+        Diagnostic::error("E2001", &format!("could not read '{}': {}", path, err))
+    }
+
+    pub fn write_error(target: &str, err: &std::io::Error) -> Diagnostic {
+        Diagnostic::error("E2002", &format!("could not write {}: {}", target, err))
+    }
+
+    pub fn not_formatted(file_name: &str) -> Diagnostic {
+        Diagnostic::error("E2003", &format!("{} is not formatted", file_name))
+            .with_help("run 'monkey fmt' without --check to rewrite it")
+    }
+
+    // rustc style output: header, location, the source line and a marker under the span
+    pub fn render(&self, file_name: &str, source: &str, color: bool) -> String {
+        let paint = |style: &str, text: &str| {
+            if color {
+                format!("{}{}{}", style, text, RESET)
+            } else {
+                text.to_string()
+            }
+        };
+
+        let severity_style = match self.severity {
+            Severity::Error => BOLD_RED,
+            Severity::Warning => BOLD,
+        };
+
+        let mut out = format!(
+            "{}{}\n",
+            paint(severity_style, &format!("{}[{}]", self.severity, self.code)),
+            paint(BOLD, &format!(": {}", self.message))
+        );
+
+        let span = match self.span {
+            Some(span) => span,
+            None => {
+                for note in &self.notes {
+                    out.push_str(&format!("{} {}: {}\n", paint(BOLD_BLUE, "="), note.kind, note.message));
+                }
+                return out;
+            }
+        };
+
+        let line_number = span.line.to_string();
+        let gutter = " ".repeat(line_number.len());
+        let line = source.lines().nth(span.line.saturating_sub(1)).unwrap_or("");
+
+        // tabs are kept so the marker lines up with the source however it's displayed
+        let prefix = line.chars()
+            .take(span.col.saturating_sub(1))
+            .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+
+        let line_rest = line.chars().count().saturating_sub(span.col.saturating_sub(1));
+        let span_len = source.get(span.start..span.end).map(|s| s.chars().count()).unwrap_or(0);
+        let width = span_len.min(line_rest).max(1);
+
+        out.push_str(&format!("{}{} {}:{}:{}\n", gutter, paint(BOLD_BLUE, "-->"), file_name, span.line, span.col));
+        out.push_str(&format!("{} {}\n", gutter, paint(BOLD_BLUE, "|")));
+        out.push_str(&format!("{} {}\n", paint(BOLD_BLUE, &format!("{} |", line_number)), line));
+        out.push_str(&format!("{} {} {}{}\n", gutter, paint(BOLD_BLUE, "|"), prefix, paint(severity_style, &"^".repeat(width))));
+
+        if !self.notes.is_empty() {
+            out.push_str(&format!("{} {}\n", gutter, paint(BOLD_BLUE, "|")));
+        }
+        for note in &self.notes {
+            out.push_str(&format!("{} {} {}: {}\n", gutter, paint(BOLD_BLUE, "="), note.kind, note.message));
+        }
+
+        out
+    }
+
+    // a single line JSON object, the format is meant to stay stable for tools
+    pub fn to_json(&self, file_name: &str) -> String {
+        let span = match self.span {
+            Some(span) => format!(
+                "{{\"file\":{},\"line\":{},\"column\":{},\"byte_start\":{},\"byte_end\":{}}}",
+                json_string(file_name), span.line, span.col, span.start, span.end
+            ),
+            None => "null".to_string()
+        };
+
+        let notes = self.notes.iter()
+            .map(|note| format!("{{\"kind\":{},\"message\":{}}}", json_string(&note.kind.to_string()), json_string(&note.message)))
+            .collect::<Vec<_>>();
+
+        format!(
+            "{{\"severity\":{},\"code\":{},\"message\":{},\"file\":{},\"span\":{},\"notes\":[{}]}}",
+            json_string(&self.severity.to_string()),
+            json_string(self.code),
+            json_string(&self.message),
+            json_string(file_name),
+            span,
+            notes.join(",")
+        )
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            ch if (ch as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => out.push(ch),
+        }
+    }
+
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::parse;
+
+    fn first_parse_error(input: &str) -> ParseError {
+        match parse(input) {
+            Err(errors) => errors[0].clone(),
+            Ok(program) => panic!("no parser errors for '{}'. got={}", input, program)
+        }
+    }
+
+    #[test]
+    fn test_render_parse_error() {
+        let input = "let x = 1;\nlet = 5;";
+        let diagnostic = Diagnostic::from_parse_error(&first_parse_error(input));
+
+        let expected = "error[E0001]: expected next token to be 'Ident', got '=' instead
+ --> main.mk:2:5
+  |
+2 | let = 5;
+  |     ^
+";
+        let rendered = diagnostic.render("main.mk", input, false);
+        assert_eq!(rendered, expected, "wrong rendered diagnostic. got=\n{}", rendered);
+    }
+
+    #[test]
+    fn test_render_with_help_and_wide_span() {
+        let input = "if (x) {\n  let y = 99999999999999999999;\n}";
+        let diagnostic = Diagnostic::from_parse_error(&first_parse_error(input));
+
+        let expected = "error[E0003]: could not parse '99999999999999999999' as integer
+ --> main.mk:2:11
+  |
+2 |   let y = 99999999999999999999;
+  |           ^^^^^^^^^^^^^^^^^^^^
+  |
+  = help: integer literals must fit in 64 bits
+";
+        let rendered = diagnostic.render("main.mk", input, false);
+        assert_eq!(rendered, expected, "wrong rendered diagnostic. got=\n{}", rendered);
+    }
+
+    #[test]
+    fn test_render_invalid_digit() {
+        let input = "let mask = 0b1021;";
+        let diagnostic = Diagnostic::from_parse_error(&first_parse_error(input));
+
+        let expected = "error[E0010]: invalid digit '2' in binary literal '0b1021'
+ --> main.mk:1:16
+  |
+1 | let mask = 0b1021;
+  |                ^
+  |
+  = help: binary literals only use the digits 0 and 1
+";
+        let rendered = diagnostic.render("main.mk", input, false);
+        assert_eq!(rendered, expected, "wrong rendered diagnostic. got=\n{}", rendered);
+    }
+
+    #[test]
+    fn test_render_multiline_span_is_cut_at_line_end() {
+        let input = "let f = fn(x) {\n\tx";
+        let diagnostic = Diagnostic::error("E0000", "boom").with_span(Span::new(14, 18, 1, 15));
+
+        let rendered = diagnostic.render("main.mk", input, false);
+        assert!(rendered.ends_with("1 | let f = fn(x) {\n  |               ^\n"), "wrong rendered diagnostic. got=\n{}", rendered);
+    }
+
+    #[test]
+    fn test_render_without_span() {
+        let diagnostic = Diagnostic::runtime_error(ErrorKind::StackOverflow, "stack overflow: maximum call depth exceeded", None);
+
+        let rendered = diagnostic.render("main.mk", "", false);
+        let expected = format!("error[E1010]: stack overflow: maximum call depth exceeded\n= note: calls can only nest {} deep\n= help: check for recursion that never reaches its base case\n", MAX_CALL_DEPTH);
+        assert_eq!(rendered, expected, "wrong rendered diagnostic. got=\n{}", rendered);
+    }
+
+    #[test]
+    fn test_render_color() {
+        let diagnostic = Diagnostic::error("E0000", "boom").with_span(Span::new(0, 1, 1, 1));
+
+        let rendered = diagnostic.render("main.mk", "x", true);
+        assert!(rendered.starts_with("\x1b[1;31merror[E0000]\x1b[0m\x1b[1m: boom\x1b[0m\n"), "wrong colored header. got={:?}", rendered);
+        assert!(rendered.contains("\x1b[1;31m^\x1b[0m"), "marker is not colored. got={:?}", rendered);
+    }
+
+    #[test]
+    fn test_to_json() {
+        let input = "if (x) {\n  let y = 99999999999999999999;\n}";
+        let diagnostic = Diagnostic::from_parse_error(&first_parse_error(input));
+
+        let expected = concat!(
+            r#"{"severity":"error","code":"E0003","message":"could not parse '99999999999999999999' as integer","file":"dir\\main.mk","#,
+            r#""span":{"file":"dir\\main.mk","line":2,"column":11,"byte_start":19,"byte_end":39},"#,
+            r#""notes":[{"kind":"help","message":"integer literals must fit in 64 bits"}]}"#
+        );
+        let json = diagnostic.to_json("dir\\main.mk");
+        assert_eq!(json, expected, "wrong json diagnostic. got={}", json);
+    }
+
+    #[test]
+    fn test_to_json_escapes_and_missing_span() {
+        let diagnostic = Diagnostic::runtime_error(ErrorKind::Output, "bad \"quote\"\\\n\u{1}", None).with_note("tab\there");
+
+        let expected = r#"{"severity":"error","code":"E1012","message":"bad \"quote\"\\\n\u0001","file":"C:\\main.mk","span":null,"notes":[{"kind":"note","message":"tab\there"}]}"#;
+        let json = diagnostic.to_json("C:\\main.mk");
+        assert_eq!(json, expected, "wrong json diagnostic. got={}", json);
+    }
+
+    #[test]
+    fn test_command_errors() {
+        let err = std::io::Error::new(std::io::ErrorKind::NotFound, "not found");
+        let tests = [
+            (Diagnostic::read_error("a.mk", &err), "error[E2001]: could not read 'a.mk': not found\n"),
+            (Diagnostic::write_error("output", &err), "error[E2002]: could not write output: not found\n"),
+            (Diagnostic::not_formatted("a.mk"), "error[E2003]: a.mk is not formatted\n= help: run 'monkey fmt' without --check to rewrite it\n"),
+        ];
+
+        for (diagnostic, expected) in tests.iter() {
+            let rendered = diagnostic.render("a.mk", "", false);
+            assert_eq!(rendered, *expected, "wrong rendered diagnostic. got=\n{}", rendered);
+        }
+
+        let json = Diagnostic::not_formatted("C:\\a.mk").to_json("C:\\a.mk");
+        let expected = r#"{"severity":"error","code":"E2003","message":"C:\\a.mk is not formatted","file":"C:\\a.mk","span":null,"notes":[{"kind":"help","message":"run 'monkey fmt' without --check to rewrite it"}]}"#;
+        assert_eq!(json, expected, "wrong json diagnostic. got={}", json);
+    }
+}
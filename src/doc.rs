@@ -0,0 +1,175 @@
+use crate::ast;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DocFormat {
+    Markdown,
+    Html,
+}
+
+// one documented top-level binding
+#[derive(Clone, Debug, PartialEq)]
+pub struct DocItem {
+    pub name: String,
+    pub params: Option<Vec<String>>,
+    pub doc: Option<String>
+}
+
+impl DocItem {
+    pub fn signature(&self) -> String {
+        match &self.params {
+            Some(params) => format!("{}({})", self.name, params.join(", ")),
+            None => self.name.clone()
+        }
+    }
+}
+
+// every top-level let in source order, functions keep their parameter list
+pub fn collect(program: &ast::Program) -> Vec<DocItem> {
+    program.statements.iter()
+        .filter_map(|stmt| match stmt {
+            ast::Statement::Let(ident, value, doc, _) => {
+                let params = match value {
+                    ast::Expression::FunctionLiteral { params, .. } => {
+                        Some(params.iter().map(|p| p.value.clone()).collect())
+                    },
+                    _ => None
+                };
+
+                Some(DocItem {
+                    name: ident.value.clone(),
+                    params,
+                    doc: doc.clone()
+                })
+            },
+            _ => None
+        })
+        .collect()
+}
+
+pub fn generate(title: &str, program: &ast::Program, format: DocFormat) -> String {
+    let items = collect(program);
+    match format {
+        DocFormat::Markdown => markdown(title, &items),
+        DocFormat::Html => html(title, &items),
+    }
+}
+
+fn markdown(title: &str, items: &[DocItem]) -> String {
+    let mut out = format!("# {}\n", title);
+
+    for item in items {
+        out.push_str(&format!("\n## `{}`\n", item.signature()));
+        if let Some(doc) = &item.doc {
+            out.push_str(&format!("\n{}\n", doc));
+        }
+    }
+
+    out
+}
+
+fn html(title: &str, items: &[DocItem]) -> String {
+    let mut out = String::from("<!DOCTYPE html>\n<html>\n<head>\n");
+    out.push_str("<meta charset=\"utf-8\">\n");
+    out.push_str(&format!("<title>{}</title>\n</head>\n<body>\n", escape_html(title)));
+    out.push_str(&format!("<h1>{}</h1>\n", escape_html(title)));
+
+    for item in items {
+        out.push_str(&format!("<h2 id=\"{}\"><code>{}</code></h2>\n", escape_html(&item.name), escape_html(&item.signature())));
+
+        if let Some(doc) = &item.doc {
+            // blank lines separate paragraphs, like in markdown
+            for paragraph in doc.split("\n\n").filter(|p| !p.trim().is_empty()) {
+                out.push_str(&format!("<p>{}</p>\n", escape_html(paragraph.trim())));
+            }
+        }
+    }
+
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+fn escape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            ch => out.push(ch),
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::parse;
+
+    const INPUT: &str = r#"
+        /// The answer to everything.
+        let answer = 42;
+
+        /// Adds `x` and `y`.
+        ///
+        /// Works for x < y & y < x.
+        let add = fn(x, y) { x + y };
+
+        let helper = fn() { answer };
+        add(1, 2);
+    "#;
+
+    fn program() -> ast::Program {
+        match parse(INPUT) {
+            Ok(program) => program,
+            Err(errors) => panic!("parser errors: {:?}", errors)
+        }
+    }
+
+    #[test]
+    fn test_collect() {
+        let items = collect(&program());
+        let signatures = items.iter().map(|item| item.signature()).collect::<Vec<_>>();
+
+        assert_eq!(signatures, vec!["answer", "add(x, y)", "helper()"], "wrong items. got={:?}", items);
+        assert_eq!(items[2].doc, None, "helper should have no docs. got={:?}", items[2].doc);
+    }
+
+    #[test]
+    fn test_markdown() {
+        let expected = "# util.mk
+
+## `answer`
+
+The answer to everything.
+
+## `add(x, y)`
+
+Adds `x` and `y`.
+
+Works for x < y & y < x.
+
+## `helper()`
+";
+        let out = generate("util.mk", &program(), DocFormat::Markdown);
+        assert_eq!(out, expected, "wrong markdown. got=\n{}", out);
+    }
+
+    #[test]
+    fn test_html() {
+        let out = generate("util.mk", &program(), DocFormat::Html);
+
+        let expected = [
+            "<title>util.mk</title>",
+            "<h2 id=\"answer\"><code>answer</code></h2>\n<p>The answer to everything.</p>\n",
+            "<h2 id=\"add\"><code>add(x, y)</code></h2>\n<p>Adds `x` and `y`.</p>\n<p>Works for x &lt; y &amp; y &lt; x.</p>\n",
+            "<h2 id=\"helper\"><code>helper()</code></h2>\n</body>",
+        ];
+        for part in expected.iter() {
+            assert!(out.contains(part), "html is missing {:?}. got=\n{}", part, out);
+        }
+    }
+}
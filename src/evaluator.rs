@@ -0,0 +1,1332 @@
+use std::cell::{Cell, RefCell};
+use std::convert::TryFrom;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::rc::Rc;
+use num_bigint::{BigInt, Sign};
+use num_traits::ToPrimitive;
+use crate::ast;
+use crate::token::Span;
+use crate::builtins::{self, BuiltinFunction};
+
+#[derive(Clone, Debug)]
+pub enum Object {
+    Integer(i64),
+    // only produced under OverflowPolicy::Promote, values that fit in an i64 are always Integer
+    BigInt(BigInt),
+    Float(f64),
+    Boolean(bool),
+    String(String),
+    Null,
+    ReturnValue(Box<Object>),
+    // the span is filled in by the innermost expression that failed
+    Error(ErrorKind, String, Option<Span>),
+    Function {
+        params: Vec<ast::Identifier>,
+        body: ast::BlockStatement,
+        env: Environment
+    },
+    Builtin(&'static str, BuiltinFunction),
+    Array(Vec<Object>),
+    Hash(HashTable),
+}
+
+impl Object {
+    pub fn type_name(&self) -> &str {
+        match self {
+            Object::Integer(_) | Object::BigInt(_) => "INTEGER",
+            Object::Float(_) => "FLOAT",
+            Object::Boolean(_) => "BOOLEAN",
+            Object::String(_) => "STRING",
+            Object::Null => "NULL",
+            Object::ReturnValue(_) => "RETURN_VALUE",
+            Object::Error(..) => "ERROR",
+            Object::Function { .. } => "FUNCTION",
+            Object::Builtin(..) => "BUILTIN",
+            Object::Array(_) => "ARRAY",
+            Object::Hash(_) => "HASH",
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        matches!(self, Object::Error(..))
+    }
+
+    // only integers, booleans and strings can be used as hash keys
+    pub fn hash_key(&self) -> Option<HashKey> {
+        match self {
+            Object::Integer(i) => Some(HashKey::Integer(*i)),
+            Object::BigInt(i) => Some(HashKey::BigInt(i.clone())),
+            Object::Boolean(b) => Some(HashKey::Boolean(*b)),
+            Object::String(s) => Some(HashKey::String(s.clone())),
+            _ => None
+        }
+    }
+
+    // like Display, but strings are quoted, which is how they're shown inside collections
+    pub fn inspect(&self) -> String {
+        match self {
+            Object::String(s) => format!("\"{}\"", ast::escape_string(s)),
+            obj => obj.to_string()
+        }
+    }
+
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Object::Null | Object::Boolean(false))
+    }
+}
+
+impl std::fmt::Display for Object {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Object::Integer(i) => write!(fmt, "{}", i),
+            Object::BigInt(i) => write!(fmt, "{}", i),
+            Object::Float(f) => write!(fmt, "{:?}", f),
+            Object::Boolean(b) => write!(fmt, "{}", b),
+            Object::String(s) => write!(fmt, "{}", s),
+            Object::Null => write!(fmt, "null"),
+            Object::ReturnValue(val) => write!(fmt, "{}", val),
+            Object::Error(_, msg, _) => write!(fmt, "ERROR: {}", msg),
+            Object::Function { params, body, .. } => {
+                let params = params.iter()
+                    .map(|p| p.to_string())
+                    .collect::<Vec<_>>();
+
+                write!(fmt, "fn({}) {}", params.join(", "), body)
+            },
+            Object::Builtin(name, _) => write!(fmt, "builtin {}", name),
+            Object::Array(elements) => {
+                let elements = elements.iter()
+                    .map(|e| e.inspect())
+                    .collect::<Vec<_>>();
+
+                write!(fmt, "[{}]", elements.join(", "))
+            },
+            Object::Hash(hash) => {
+                let pairs = hash.iter()
+                    .map(|(key, value)| format!("{}: {}", key.inspect(), value.inspect()))
+                    .collect::<Vec<_>>();
+
+                write!(fmt, "{{{}}}", pairs.join(", "))
+            },
+        }
+    }
+}
+
+// what went wrong at runtime, each kind has its own diagnostic code
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ErrorKind {
+    UnknownIdentifier,
+    TypeMismatch,
+    UnknownOperator,
+    DivisionByZero,
+    IntegerOverflow,
+    NotAFunction,
+    WrongArgumentCount,
+    UnsupportedIndex,
+    UnhashableKey,
+    StackOverflow,
+    // a builtin got an argument of the wrong type, or a value it can't convert
+    InvalidArgument,
+    // program output couldn't be written
+    Output,
+}
+
+impl ErrorKind {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ErrorKind::UnknownIdentifier => "E1001",
+            ErrorKind::TypeMismatch => "E1002",
+            ErrorKind::UnknownOperator => "E1003",
+            ErrorKind::DivisionByZero => "E1004",
+            ErrorKind::IntegerOverflow => "E1005",
+            ErrorKind::NotAFunction => "E1006",
+            ErrorKind::WrongArgumentCount => "E1007",
+            ErrorKind::UnsupportedIndex => "E1008",
+            ErrorKind::UnhashableKey => "E1009",
+            ErrorKind::StackOverflow => "E1010",
+            ErrorKind::InvalidArgument => "E1011",
+            ErrorKind::Output => "E1012",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum HashKey {
+    Integer(i64),
+    BigInt(BigInt),
+    Boolean(bool),
+    String(String),
+}
+
+// the pairs of a hash object, kept in insertion order so printing and iterating are stable
+#[derive(Clone, Debug, Default)]
+pub struct HashTable {
+    pairs: Vec<(Object, Object)>,
+    index: HashMap<HashKey, usize>
+}
+
+impl HashTable {
+    pub fn new() -> HashTable {
+        HashTable::default()
+    }
+
+    // a key that's already there keeps its position and gets the new value
+    pub fn insert(&mut self, key: HashKey, key_obj: Object, value: Object) {
+        match self.index.get(&key) {
+            Some(&i) => self.pairs[i].1 = value,
+            None => {
+                self.index.insert(key, self.pairs.len());
+                self.pairs.push((key_obj, value));
+            }
+        }
+    }
+
+    pub fn get(&self, key: &HashKey) -> Option<&Object> {
+        self.index.get(key).map(|&i| &self.pairs[i].1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(Object, Object)> {
+        self.pairs.iter()
+    }
+}
+
+// what integer arithmetic does when the result doesn't fit in an i64
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OverflowPolicy {
+    // a runtime error
+    #[default]
+    Checked,
+    // two's complement wrap around
+    Wrapping,
+    // switch to an arbitrary precision integer
+    Promote,
+}
+
+// deeper calls are an error instead of overflowing the native stack
+pub const MAX_CALL_DEPTH: usize = 2000;
+
+// every call recurses through the evaluator, and a debug build uses tens of kilobytes of
+// stack per call, so programs need a bigger stack than a thread gets by default
+pub const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+// state shared by every scope of a program
+struct Runtime {
+    overflow: OverflowPolicy,
+    depth: Cell<usize>,
+    // where puts writes to
+    output: Rc<RefCell<dyn Write>>
+}
+
+struct Scope {
+    store: HashMap<String, Object>,
+    outer: Option<Environment>,
+    runtime: Rc<Runtime>
+}
+
+// a handle to a scope, clones share the same bindings so closures keep their defining scope alive.
+// known limitation: a function bound in the scope it captured, which is every named function,
+// is a reference cycle, so that scope is never freed. a program's global scope lives until the
+// process exits anyway, but each call that defines a named function inside it leaks its frame
+#[derive(Clone)]
+pub struct Environment {
+    scope: Rc<RefCell<Scope>>
+}
+
+impl Environment {
+    pub fn new() -> Environment {
+        Environment::with_overflow_policy(OverflowPolicy::default())
+    }
+
+    pub fn with_overflow_policy(overflow: OverflowPolicy) -> Environment {
+        Environment::with_output(overflow, Rc::new(RefCell::new(io::stdout())))
+    }
+
+    pub fn with_output(overflow: OverflowPolicy, output: Rc<RefCell<dyn Write>>) -> Environment {
+        Environment {
+            scope: Rc::new(RefCell::new(Scope {
+                store: HashMap::new(),
+                outer: None,
+                runtime: Rc::new(Runtime {
+                    overflow,
+                    depth: Cell::new(0),
+                    output
+                })
+            }))
+        }
+    }
+
+    // enclosed scopes share the runtime of the scope they were created in
+    pub fn new_enclosed(outer: &Environment) -> Environment {
+        Environment {
+            scope: Rc::new(RefCell::new(Scope {
+                store: HashMap::new(),
+                outer: Some(outer.clone()),
+                runtime: outer.runtime()
+            }))
+        }
+    }
+
+    fn runtime(&self) -> Rc<Runtime> {
+        Rc::clone(&self.scope.borrow().runtime)
+    }
+
+    pub fn overflow_policy(&self) -> OverflowPolicy {
+        self.scope.borrow().runtime.overflow
+    }
+
+    // program output, as opposed to the value the program evaluates to
+    pub fn print(&self, text: &str) -> io::Result<()> {
+        let runtime = self.runtime();
+        let mut output = runtime.output.borrow_mut();
+        output.write_all(text.as_bytes())?;
+        output.flush()
+    }
+
+    pub fn get(&self, name: &str) -> Option<Object> {
+        let scope = self.scope.borrow();
+        match scope.store.get(name) {
+            Some(obj) => Some(obj.clone()),
+            None => scope.outer.as_ref().and_then(|outer| outer.get(name))
+        }
+    }
+
+    pub fn set(&mut self, name: &str, val: Object) {
+        self.scope.borrow_mut().store.insert(name.to_string(), val);
+    }
+}
+
+impl Default for Environment {
+    fn default() -> Environment {
+        Environment::new()
+    }
+}
+
+// a function bound in the scope it captures makes the environment reference itself,
+// so only the names are printed to avoid recursing forever
+impl std::fmt::Debug for Environment {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let scope = self.scope.borrow();
+        let mut names = scope.store.keys().collect::<Vec<_>>();
+        names.sort();
+
+        fmt.debug_struct("Environment")
+            .field("names", &names)
+            .field("outer", &scope.outer)
+            .finish()
+    }
+}
+
+fn new_error(kind: ErrorKind, msg: String) -> Object {
+    Object::Error(kind, msg, None)
+}
+
+pub fn eval(program: &ast::Program, env: &mut Environment) -> Object {
+    let mut result = Object::Null;
+
+    for stmt in &program.statements {
+        result = eval_statement(stmt, env);
+
+        match result {
+            Object::ReturnValue(val) => return *val,
+            Object::Error(..) => return result,
+            _ => {}
+        }
+    }
+
+    result
+}
+
+fn eval_block_statement(block: &ast::BlockStatement, env: &mut Environment) -> Object {
+    let mut result = Object::Null;
+
+    for stmt in &block.statements {
+        result = eval_statement(stmt, env);
+
+        // return values are unwrapped by the outermost caller, so they can bubble up through nested blocks
+        if let Object::ReturnValue(_) | Object::Error(..) = result {
+            return result;
+        }
+    }
+
+    result
+}
+
+fn eval_statement(stmt: &ast::Statement, env: &mut Environment) -> Object {
+    match stmt {
+        ast::Statement::Let(ident, exp, ..) => {
+            let val = eval_expression(exp, env);
+            if val.is_error() {
+                return val;
+            }
+
+            env.set(&ident.value, val);
+            Object::Null
+        },
+        ast::Statement::Return(exp, _) => {
+            let val = eval_expression(exp, env);
+            if val.is_error() {
+                return val;
+            }
+
+            Object::ReturnValue(Box::new(val))
+        },
+        ast::Statement::Expression(exp, _) => eval_expression(exp, env),
+    }
+}
+
+fn eval_expression(exp: &ast::Expression, env: &mut Environment) -> Object {
+    match eval_expression_node(exp, env) {
+        Object::Error(kind, msg, None) => Object::Error(kind, msg, Some(exp.span())),
+        obj => obj
+    }
+}
+
+fn eval_expression_node(exp: &ast::Expression, env: &mut Environment) -> Object {
+    match exp {
+        ast::Expression::Identifier(ident) => eval_identifier(ident, env),
+        ast::Expression::Literal(lit, _) => eval_literal(lit),
+        ast::Expression::Prefix { operator, right, .. } => {
+            let right = eval_expression(right, env);
+            if right.is_error() {
+                return right;
+            }
+
+            eval_prefix_expression(operator, right, env.overflow_policy())
+        },
+        ast::Expression::Infix { left, operator, right, .. } => {
+            let left = eval_expression(left, env);
+            if left.is_error() {
+                return left;
+            }
+
+            let right = eval_expression(right, env);
+            if right.is_error() {
+                return right;
+            }
+
+            eval_infix_expression(operator, left, right, env.overflow_policy())
+        },
+        ast::Expression::If { condition, consequence, alternative, .. } => {
+            let condition = eval_expression(condition, env);
+            if condition.is_error() {
+                return condition;
+            }
+
+            if condition.is_truthy() {
+                eval_block_statement(consequence, env)
+            } else if let Some(alt) = alternative {
+                eval_block_statement(alt, env)
+            } else {
+                Object::Null
+            }
+        },
+        ast::Expression::FunctionLiteral { params, body, .. } => {
+            Object::Function {
+                params: params.clone(),
+                body: body.clone(),
+                env: env.clone()
+            }
+        },
+        ast::Expression::Call { function, arguments, .. } => {
+            let function = eval_expression(function, env);
+            if function.is_error() {
+                return function;
+            }
+
+            match eval_expressions(arguments, env) {
+                Ok(args) => apply_function(function, args, env),
+                Err(err) => err
+            }
+        },
+        ast::Expression::ArrayLiteral { elements, .. } => {
+            match eval_expressions(elements, env) {
+                Ok(elements) => Object::Array(elements),
+                Err(err) => err
+            }
+        },
+        ast::Expression::Index { left, index, .. } => {
+            let left = eval_expression(left, env);
+            if left.is_error() {
+                return left;
+            }
+
+            let index = eval_expression(index, env);
+            if index.is_error() {
+                return index;
+            }
+
+            eval_index_expression(left, index)
+        },
+        ast::Expression::Hash { pairs, .. } => eval_hash_literal(pairs, env),
+    }
+}
+
+fn eval_hash_literal(pairs: &[(ast::Expression, ast::Expression)], env: &mut Environment) -> Object {
+    let mut hash = HashTable::new();
+
+    for (key_exp, value_exp) in pairs {
+        let key = eval_expression(key_exp, env);
+        if key.is_error() {
+            return key;
+        }
+
+        let hash_key = match key.hash_key() {
+            Some(hash_key) => hash_key,
+            None => return Object::Error(ErrorKind::UnhashableKey, format!("unusable as hash key: {}", key.type_name()), Some(key_exp.span()))
+        };
+
+        let value = eval_expression(value_exp, env);
+        if value.is_error() {
+            return value;
+        }
+
+        hash.insert(hash_key, key, value);
+    }
+
+    Object::Hash(hash)
+}
+
+// evaluates left to right and stops at the first error
+fn eval_expressions(exps: &[ast::Expression], env: &mut Environment) -> Result<Vec<Object>, Object> {
+    let mut result = vec![];
+    for exp in exps {
+        let val = eval_expression(exp, env);
+        if val.is_error() {
+            return Err(val);
+        }
+        result.push(val);
+    }
+
+    Ok(result)
+}
+
+// indexes outside the array, negative ones included, evaluate to null
+fn eval_index_expression(left: Object, index: Object) -> Object {
+    match (&left, &index) {
+        (Object::Array(elements), Object::Integer(i)) => {
+            usize::try_from(*i).ok()
+                .and_then(|i| elements.get(i))
+                .cloned()
+                .unwrap_or(Object::Null)
+        },
+        (Object::Array(_), Object::BigInt(_)) => Object::Null,
+        // a missing key is null, like an index outside an array
+        (Object::Hash(hash), _) => match index.hash_key() {
+            Some(key) => hash.get(&key).cloned().unwrap_or(Object::Null),
+            None => new_error(ErrorKind::UnhashableKey, format!("unusable as hash key: {}", index.type_name()))
+        },
+        _ => new_error(ErrorKind::UnsupportedIndex, format!("index operator not supported: {}[{}]", left.type_name(), index.type_name()))
+    }
+}
+
+fn eval_identifier(ident: &ast::Identifier, env: &Environment) -> Object {
+    match env.get(&ident.value) {
+        Some(val) => val,
+        None => match builtins::lookup(&ident.value) {
+            Some(builtin) => builtin,
+            None => new_error(ErrorKind::UnknownIdentifier, format!("identifier not found: {}", ident.value))
+        }
+    }
+}
+
+fn eval_literal(lit: &ast::Literal) -> Object {
+    match lit {
+        ast::Literal::Int(i) => Object::Integer(*i),
+        ast::Literal::BigInt(i) => Object::BigInt(i.clone()),
+        ast::Literal::Float(f) => Object::Float(*f),
+        ast::Literal::Bool(b) => Object::Boolean(*b),
+        ast::Literal::String(s) => Object::String(s.clone()),
+    }
+}
+
+fn eval_prefix_expression(operator: &str, right: Object, overflow: OverflowPolicy) -> Object {
+    match operator {
+        "!" => Object::Boolean(!right.is_truthy()),
+        "-" => match right {
+            Object::Integer(i) => match i.checked_neg() {
+                Some(val) => Object::Integer(val),
+                None => integer_overflow(overflow, format!("-{}", i), i.wrapping_neg(), || -BigInt::from(i))
+            },
+            Object::BigInt(i) => normalize_bigint(-i),
+            Object::Float(f) => Object::Float(-f),
+            _ => new_error(ErrorKind::UnknownOperator, format!("unknown operator: -{}", right.type_name()))
+        },
+        _ => new_error(ErrorKind::UnknownOperator, format!("unknown operator: {}{}", operator, right.type_name()))
+    }
+}
+
+fn eval_infix_expression(operator: &str, left: Object, right: Object, overflow: OverflowPolicy) -> Object {
+    match (&left, &right) {
+        (Object::Integer(l), Object::Integer(r)) => eval_integer_infix_expression(operator, *l, *r, overflow),
+        (Object::BigInt(l), Object::BigInt(r)) => eval_bigint_infix_expression(operator, l.clone(), r.clone()),
+        (Object::Integer(l), Object::BigInt(r)) => eval_bigint_infix_expression(operator, BigInt::from(*l), r.clone()),
+        (Object::BigInt(l), Object::Integer(r)) => eval_bigint_infix_expression(operator, l.clone(), BigInt::from(*r)),
+        // an integer meeting a float is promoted, the result is always a float
+        (Object::Float(l), Object::Float(r)) => eval_float_infix_expression(operator, *l, *r),
+        (Object::Integer(l), Object::Float(r)) => eval_float_infix_expression(operator, *l as f64, *r),
+        (Object::Float(l), Object::Integer(r)) => eval_float_infix_expression(operator, *l, *r as f64),
+        (Object::BigInt(l), Object::Float(r)) => eval_float_infix_expression(operator, bigint_to_f64(l), *r),
+        (Object::Float(l), Object::BigInt(r)) => eval_float_infix_expression(operator, *l, bigint_to_f64(r)),
+        (Object::Boolean(l), Object::Boolean(r)) => match operator {
+            "==" => Object::Boolean(l == r),
+            "!=" => Object::Boolean(l != r),
+            _ => new_error(ErrorKind::UnknownOperator, format!("unknown operator: {} {} {}", left.type_name(), operator, right.type_name()))
+        },
+        (Object::String(l), Object::String(r)) => match operator {
+            "+" => Object::String(format!("{}{}", l, r)),
+            "==" => Object::Boolean(l == r),
+            "!=" => Object::Boolean(l != r),
+            _ => new_error(ErrorKind::UnknownOperator, format!("unknown operator: {} {} {}", left.type_name(), operator, right.type_name()))
+        },
+        (Object::Null, Object::Null) => match operator {
+            "==" => Object::Boolean(true),
+            "!=" => Object::Boolean(false),
+            _ => new_error(ErrorKind::UnknownOperator, format!("unknown operator: {} {} {}", left.type_name(), operator, right.type_name()))
+        },
+        _ => {
+            if left.type_name() != right.type_name() {
+                new_error(ErrorKind::TypeMismatch, format!("type mismatch: {} {} {}", left.type_name(), operator, right.type_name()))
+            } else {
+                new_error(ErrorKind::UnknownOperator, format!("unknown operator: {} {} {}", left.type_name(), operator, right.type_name()))
+            }
+        }
+    }
+}
+
+fn eval_integer_infix_expression(operator: &str, left: i64, right: i64, overflow: OverflowPolicy) -> Object {
+    let (result, wrapped) = match operator {
+        "+" => (left.checked_add(right), left.wrapping_add(right)),
+        "-" => (left.checked_sub(right), left.wrapping_sub(right)),
+        "*" => (left.checked_mul(right), left.wrapping_mul(right)),
+        "/" => {
+            if right == 0 {
+                return new_error(ErrorKind::DivisionByZero, format!("division by zero: {} / {}", left, right));
+            }
+            (left.checked_div(right), left.wrapping_div(right))
+        },
+        "<" => return Object::Boolean(left < right),
+        ">" => return Object::Boolean(left > right),
+        "==" => return Object::Boolean(left == right),
+        "!=" => return Object::Boolean(left != right),
+        _ => return new_error(ErrorKind::UnknownOperator, format!("unknown operator: INTEGER {} INTEGER", operator))
+    };
+
+    match result {
+        Some(val) => Object::Integer(val),
+        None => integer_overflow(
+            overflow,
+            format!("{} {} {}", left, operator, right),
+            wrapped,
+            || match eval_bigint_infix_expression(operator, BigInt::from(left), BigInt::from(right)) {
+                Object::BigInt(val) => val,
+                obj => unreachable!("an overflowing operation fits in an i64. got={:?}", obj)
+            }
+        )
+    }
+}
+
+// what an overflowing operation evaluates to, the wide result is only computed when promoting
+fn integer_overflow<F: FnOnce() -> BigInt>(overflow: OverflowPolicy, expression: String, wrapped: i64, promoted: F) -> Object {
+    match overflow {
+        OverflowPolicy::Checked => new_error(ErrorKind::IntegerOverflow, format!("integer overflow: {}", expression)),
+        OverflowPolicy::Wrapping => Object::Integer(wrapped),
+        OverflowPolicy::Promote => Object::BigInt(promoted()),
+    }
+}
+
+fn eval_bigint_infix_expression(operator: &str, left: BigInt, right: BigInt) -> Object {
+    match operator {
+        "+" => normalize_bigint(left + right),
+        "-" => normalize_bigint(left - right),
+        "*" => normalize_bigint(left * right),
+        "/" => {
+            if right.sign() == Sign::NoSign {
+                return new_error(ErrorKind::DivisionByZero, format!("division by zero: {} / {}", left, right));
+            }
+            normalize_bigint(left / right)
+        },
+        "<" => Object::Boolean(left < right),
+        ">" => Object::Boolean(left > right),
+        "==" => Object::Boolean(left == right),
+        "!=" => Object::Boolean(left != right),
+        _ => new_error(ErrorKind::UnknownOperator, format!("unknown operator: INTEGER {} INTEGER", operator))
+    }
+}
+
+// results that fit are turned back into plain integers, so equal values look the same
+fn normalize_bigint(val: BigInt) -> Object {
+    match val.to_i64() {
+        Some(i) => Object::Integer(i),
+        None => Object::BigInt(val)
+    }
+}
+
+fn bigint_to_f64(val: &BigInt) -> f64 {
+    val.to_f64().unwrap_or(f64::NAN)
+}
+
+// follows IEEE 754, so dividing by zero gives inf or NaN instead of an error
+fn eval_float_infix_expression(operator: &str, left: f64, right: f64) -> Object {
+    match operator {
+        "+" => Object::Float(left + right),
+        "-" => Object::Float(left - right),
+        "*" => Object::Float(left * right),
+        "/" => Object::Float(left / right),
+        "<" => Object::Boolean(left < right),
+        ">" => Object::Boolean(left > right),
+        "==" => Object::Boolean(left == right),
+        "!=" => Object::Boolean(left != right),
+        _ => new_error(ErrorKind::UnknownOperator, format!("unknown operator: FLOAT {} FLOAT", operator))
+    }
+}
+
+fn apply_function(function: Object, args: Vec<Object>, caller: &Environment) -> Object {
+    match function {
+        Object::Function { params, body, env } => {
+            if params.len() != args.len() {
+                return new_error(ErrorKind::WrongArgumentCount, format!("wrong number of arguments: expected {}, got {}", params.len(), args.len()));
+            }
+
+            let runtime = env.runtime();
+            if runtime.depth.get() >= MAX_CALL_DEPTH {
+                return new_error(ErrorKind::StackOverflow, "stack overflow: maximum call depth exceeded".to_string());
+            }
+
+            let mut extended_env = Environment::new_enclosed(&env);
+            for (param, arg) in params.iter().zip(args) {
+                extended_env.set(&param.value, arg);
+            }
+
+            runtime.depth.set(runtime.depth.get() + 1);
+            let result = eval_block_statement(&body, &mut extended_env);
+            runtime.depth.set(runtime.depth.get() - 1);
+
+            match result {
+                Object::ReturnValue(val) => *val,
+                obj => obj
+            }
+        },
+        Object::Builtin(_, func) => func(args, caller),
+        _ => new_error(ErrorKind::NotAFunction, format!("not a function: {}", function.type_name()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::{parse, Parser};
+
+    fn test_eval(input: &str) -> Object {
+        test_eval_with(input, OverflowPolicy::Checked)
+    }
+
+    fn test_eval_with(input: &str, overflow: OverflowPolicy) -> Object {
+        let l = Lexer::new(input);
+        let mut p = Parser::new(l).with_big_integers(overflow == OverflowPolicy::Promote);
+        let program = match p.parse_program() {
+            Ok(program) => program,
+            Err(errors) => panic!("parser has {} errors: {:?}", errors.len(), errors)
+        };
+
+        let mut env = Environment::with_overflow_policy(overflow);
+        eval(&program, &mut env)
+    }
+
+    fn test_integer_object(obj: &Object, expected: i64) {
+        match obj {
+            Object::Integer(i) => assert_eq!(*i, expected, "object has wrong value. expected={}, got={}", expected, i),
+            _ => panic!("object is not Integer. got={:?}", obj)
+        }
+    }
+
+    fn test_float_object(obj: &Object, expected: f64) {
+        match obj {
+            Object::Float(f) => assert_eq!(*f, expected, "object has wrong value. expected={}, got={}", expected, f),
+            _ => panic!("object is not Float. got={:?}", obj)
+        }
+    }
+
+    fn test_boolean_object(obj: &Object, expected: bool) {
+        match obj {
+            Object::Boolean(b) => assert_eq!(*b, expected, "object has wrong value. expected={}, got={}", expected, b),
+            _ => panic!("object is not Boolean. got={:?}", obj)
+        }
+    }
+
+    fn test_null_object(obj: &Object) {
+        assert!(matches!(obj, Object::Null), "object is not Null. got={:?}", obj);
+    }
+
+    #[test]
+    fn test_eval_integer_expression() {
+        let tests = [
+            ("5", 5),
+            ("10", 10),
+            ("-5", -5),
+            ("-10", -10),
+            ("5 + 5 + 5 + 5 - 10", 10),
+            ("2 * 2 * 2 * 2 * 2", 32),
+            ("-50 + 100 + -50", 0),
+            ("5 * 2 + 10", 20),
+            ("5 + 2 * 10", 25),
+            ("20 + 2 * -10", 0),
+            ("50 / 2 * 2 + 10", 60),
+            ("2 * (5 + 10)", 30),
+            ("3 * 3 * 3 + 10", 37),
+            ("3 * (3 * 3) + 10", 37),
+            ("(5 + 10 * 2 + 15 / 3) * 2 + -10", 50),
+        ];
+
+        for (input, expected) in tests.iter() {
+            test_integer_object(&test_eval(input), *expected);
+        }
+    }
+
+    #[test]
+    fn test_array_literals() {
+        match test_eval("[1, 2 * 2, 3 + 3]") {
+            Object::Array(elements) => {
+                assert_eq!(elements.len(), 3, "array has wrong num of elements. got={}", elements.len());
+                test_integer_object(&elements[0], 1);
+                test_integer_object(&elements[1], 4);
+                test_integer_object(&elements[2], 6);
+            },
+            obj => panic!("object is not Array. got={:?}", obj)
+        }
+
+        let evaluated = test_eval("[1, \"two\", [3.0, true], fn(x) { x }, []]").to_string();
+        let expected = "[1, \"two\", [3.0, true], fn(x) { x; }, []]";
+        assert_eq!(evaluated, expected, "wrong display. expected={}, got={}", expected, evaluated);
+    }
+
+    #[test]
+    fn test_array_index_expressions() {
+        let tests = [
+            ("[1, 2, 3][0]", Some(1)),
+            ("[1, 2, 3][1]", Some(2)),
+            ("[1, 2, 3][2]", Some(3)),
+            ("let i = 0; [1][i];", Some(1)),
+            ("[1, 2, 3][1 + 1];", Some(3)),
+            ("let myArray = [1, 2, 3]; myArray[2];", Some(3)),
+            ("let myArray = [1, 2, 3]; myArray[0] + myArray[1] + myArray[2];", Some(6)),
+            ("let myArray = [1, 2, 3]; let i = myArray[0]; myArray[i]", Some(2)),
+            ("[[1, 2], [3]][1][0]", Some(3)),
+            // out of bounds, negative indexes included, gives null
+            ("[1, 2, 3][3]", None),
+            ("[1, 2, 3][-1]", None),
+            ("[][0]", None),
+        ];
+
+        for (input, expected) in tests.iter() {
+            let evaluated = test_eval(input);
+            match expected {
+                Some(i) => test_integer_object(&evaluated, *i),
+                None => test_null_object(&evaluated)
+            }
+        }
+
+        let evaluated = test_eval_with("[1][9223372036854775807 * 2]", OverflowPolicy::Promote);
+        test_null_object(&evaluated);
+    }
+
+    #[test]
+    fn test_error_kinds() {
+        let tests = [
+            ("foo", ErrorKind::UnknownIdentifier),
+            ("1 + true", ErrorKind::TypeMismatch),
+            ("-\"a\"", ErrorKind::UnknownOperator),
+            ("1 / 0", ErrorKind::DivisionByZero),
+            ("9223372036854775807 + 1", ErrorKind::IntegerOverflow),
+            ("1(2)", ErrorKind::NotAFunction),
+            ("fn(x) { x }()", ErrorKind::WrongArgumentCount),
+            ("len()", ErrorKind::WrongArgumentCount),
+            ("1[0]", ErrorKind::UnsupportedIndex),
+            ("{[]: 1}", ErrorKind::UnhashableKey),
+            ("first(1)", ErrorKind::InvalidArgument),
+            ("int(\"x\")", ErrorKind::InvalidArgument),
+        ];
+
+        for (input, expected) in tests.iter() {
+            match test_eval(input) {
+                Object::Error(kind, ..) => assert_eq!(kind, *expected, "wrong error kind for {}. expected={:?}, got={:?}", input, expected, kind),
+                obj => panic!("no error object returned for {}. got={:?}", input, obj)
+            }
+        }
+    }
+
+    #[test]
+    fn test_call_depth_limit() {
+        // the test thread's own stack is too small to get anywhere near the limit
+        let thread = std::thread::Builder::new().stack_size(STACK_SIZE).spawn(|| {
+            let countdown = "let c = fn(n) { if (n == 0) { 0 } else { c(n - 1) } };";
+            let deepest = test_eval(&format!("{} c({})", countdown, MAX_CALL_DEPTH - 1)).to_string();
+            let too_deep = test_eval(&format!("{} c({})", countdown, MAX_CALL_DEPTH)).to_string();
+
+            // the depth unwinds with the error, so the same environment can keep calling functions
+            let mut env = Environment::new();
+            let forever = eval(&parse("let f = fn(x) { f(x) }; f(1)").unwrap(), &mut env).to_string();
+            let after = eval(&parse("let g = fn(x) { x }; g(2)").unwrap(), &mut env).to_string();
+
+            (deepest, too_deep, forever, after)
+        });
+        let (deepest, too_deep, forever, after) = match thread.map(|handle| handle.join()) {
+            Ok(Ok(results)) => results,
+            _ => panic!("evaluator thread failed")
+        };
+
+        let expected = "ERROR: stack overflow: maximum call depth exceeded";
+        assert_eq!(deepest, "0", "calls up to the limit should work. got={}", deepest);
+        assert_eq!(too_deep, expected, "wrong result past the limit. expected={}, got={}", expected, too_deep);
+        assert_eq!(forever, expected, "wrong result for unbounded recursion. expected={}, got={}", expected, forever);
+        assert_eq!(after, "2", "calls should work after a stack overflow. got={}", after);
+    }
+
+    #[test]
+    fn test_hash_literals() {
+        let input = "let two = \"two\";
+{
+    \"one\": 10 - 9,
+    two: 1 + 1,
+    \"thr\" + \"ee\": 6 / 2,
+    4: 4,
+    true: 5,
+    false: 6
+}";
+        match test_eval(input) {
+            Object::Hash(hash) => {
+                let expected = [
+                    (Object::String("one".to_string()), 1),
+                    (Object::String("two".to_string()), 2),
+                    (Object::String("three".to_string()), 3),
+                    (Object::Integer(4), 4),
+                    (Object::Boolean(true), 5),
+                    (Object::Boolean(false), 6),
+                ];
+                assert_eq!(hash.len(), expected.len(), "hash has wrong num of pairs. got={}", hash.len());
+                for (key, value) in expected.iter() {
+                    match hash.get(&key.hash_key().unwrap()) {
+                        Some(obj) => test_integer_object(obj, *value),
+                        None => panic!("no pair for given key in pairs. key={}", key)
+                    }
+                }
+            },
+            obj => panic!("eval didn't return Hash. got={:?}", obj)
+        }
+
+        // pairs print in insertion order, a repeated key keeps its first position
+        let evaluated = test_eval("{\"b\": 1, 2: [true], \"a\": {}, \"b\": 3}").to_string();
+        let expected = "{\"b\": 3, 2: [true], \"a\": {}}";
+        assert_eq!(evaluated, expected, "wrong display. expected={}, got={}", expected, evaluated);
+    }
+
+    #[test]
+    fn test_hash_index_expressions() {
+        let tests = [
+            ("{\"foo\": 5}[\"foo\"]", Some(5)),
+            ("{\"foo\": 5}[\"bar\"]", None),
+            ("let key = \"foo\"; {\"foo\": 5}[key]", Some(5)),
+            ("{}[\"foo\"]", None),
+            ("{5: 5}[5]", Some(5)),
+            ("{true: 5}[true]", Some(5)),
+            ("{false: 5}[false]", Some(5)),
+            ("{1: 5}[true]", None),
+        ];
+
+        for (input, expected) in tests.iter() {
+            let evaluated = test_eval(input);
+            match expected {
+                Some(i) => test_integer_object(&evaluated, *i),
+                None => test_null_object(&evaluated)
+            }
+        }
+    }
+
+    #[test]
+    fn test_eval_64_bit_integers() {
+        let tests = [
+            ("3000000000", 3000000000),
+            ("9223372036854775807", i64::MAX),
+            ("-9223372036854775807 - 1", i64::MIN),
+            ("100000 * 100000", 10000000000),
+        ];
+
+        for (input, expected) in tests.iter() {
+            test_integer_object(&test_eval(input), *expected);
+        }
+    }
+
+    #[test]
+    fn test_overflow_checked() {
+        let tests = [
+            ("9223372036854775807 + 1", "integer overflow: 9223372036854775807 + 1"),
+            ("-9223372036854775807 - 2", "integer overflow: -9223372036854775807 - 2"),
+            ("4294967296 * 4294967296", "integer overflow: 4294967296 * 4294967296"),
+            ("let min = -9223372036854775807 - 1; min / -1", "integer overflow: -9223372036854775808 / -1"),
+            ("let min = -9223372036854775807 - 1; -min", "integer overflow: --9223372036854775808"),
+        ];
+
+        for (input, expected) in tests.iter() {
+            match test_eval(input) {
+                Object::Error(_, msg, _) => assert_eq!(msg, *expected, "wrong error message. expected={}, got={}", expected, msg),
+                obj => panic!("no error object returned. got={:?}", obj)
+            }
+        }
+    }
+
+    #[test]
+    fn test_overflow_wrapping() {
+        let tests = [
+            ("9223372036854775807 + 1", i64::MIN),
+            ("-9223372036854775807 - 3", i64::MAX - 1),
+            ("4294967296 * 4294967296", 0),
+            ("let min = -9223372036854775807 - 1; min / -1", i64::MIN),
+            ("let min = -9223372036854775807 - 1; -min", i64::MIN),
+        ];
+
+        for (input, expected) in tests.iter() {
+            test_integer_object(&test_eval_with(input, OverflowPolicy::Wrapping), *expected);
+        }
+
+        let evaluated = test_eval_with("1 / 0", OverflowPolicy::Wrapping);
+        assert!(evaluated.is_error(), "division by zero should still fail. got={:?}", evaluated);
+    }
+
+    #[test]
+    fn test_overflow_promote() {
+        let tests = [
+            ("9223372036854775807 + 1", "9223372036854775808"),
+            ("4294967296 * 4294967296", "18446744073709551616"),
+            ("let min = -9223372036854775807 - 1; -min", "9223372036854775808"),
+            ("let big = 9223372036854775807 * 10; big * big", "8507059173023461584739690778423250124900"),
+            ("let big = 9223372036854775807 * 10; big / 5 > 9223372036854775807", "true"),
+            ("let big = 9223372036854775807 * 10; big - big", "0"),
+            ("let big = 9223372036854775807 + 1; big * 0.5", "4.611686018427388e18"),
+            // literals can be big too
+            ("9223372036854775808", "9223372036854775808"),
+            ("0xffff_ffff_ffff_ffff_ff + 1", "4722366482869645213696"),
+            ("99999999999999999999 - 99999999999999999998", "1"),
+            ("-9223372036854775808 == -9223372036854775807 - 1", "true"),
+        ];
+
+        for (input, expected) in tests.iter() {
+            let evaluated = test_eval_with(input, OverflowPolicy::Promote).to_string();
+            assert_eq!(evaluated, *expected, "wrong result for {}. expected={}, got={}", input, expected, evaluated);
+        }
+
+        // results that fit in an i64 again go back to plain integers
+        let evaluated = test_eval_with("let big = 9223372036854775807 + 1; big - 2", OverflowPolicy::Promote);
+        test_integer_object(&evaluated, i64::MAX - 1);
+
+        let evaluated = test_eval_with("let big = 9223372036854775807 + 1; big / (big - big)", OverflowPolicy::Promote);
+        assert!(evaluated.is_error(), "division by zero should still fail. got={:?}", evaluated);
+
+        let evaluated = test_eval_with("let add = fn(a, b) { a + b }; add(9223372036854775807, 1)", OverflowPolicy::Promote);
+        assert_eq!(evaluated.type_name(), "INTEGER", "functions should inherit the policy. got={:?}", evaluated);
+        assert!(matches!(evaluated, Object::BigInt(_)), "functions should inherit the policy. got={:?}", evaluated);
+    }
+
+    #[test]
+    fn test_eval_float_expression() {
+        let tests = [
+            ("3.5", 3.5),
+            ("-2.5", -2.5),
+            ("1.5 + 2.25", 3.75),
+            ("0.5 * 4.0 - 1.0", 1.0),
+            ("1.0 / 4.0", 0.25),
+            // an integer on either side is promoted to float
+            ("1 + 0.5", 1.5),
+            ("0.5 + 1", 1.5),
+            ("7 / 2.0", 3.5),
+            ("2 * (1 + .5)", 3.0),
+            ("-(3 - 3.5)", 0.5),
+        ];
+
+        for (input, expected) in tests.iter() {
+            test_float_object(&test_eval(input), *expected);
+        }
+
+        // integer division stays integer division
+        test_integer_object(&test_eval("7 / 2"), 3);
+
+        let evaluated = test_eval("1.0 / 0");
+        assert!(matches!(evaluated, Object::Float(f) if f == f64::INFINITY), "1.0 / 0 should be inf. got={:?}", evaluated);
+        let evaluated = test_eval("0.0 / 0.0");
+        assert!(matches!(evaluated, Object::Float(f) if f.is_nan()), "0.0 / 0.0 should be NaN. got={:?}", evaluated);
+    }
+
+    #[test]
+    fn test_float_comparisons() {
+        let tests = [
+            ("1.5 < 2", true),
+            ("2 > 1.5", true),
+            ("1 == 1.0", true),
+            ("0.1 + 0.2 == 0.3", false),
+            ("2.0 != 2", false),
+            ("!0.0", false),
+        ];
+
+        for (input, expected) in tests.iter() {
+            test_boolean_object(&test_eval(input), *expected);
+        }
+    }
+
+    #[test]
+    fn test_float_display() {
+        let tests = [
+            ("3.0", "3.0"),
+            ("2 * 1.25", "2.5"),
+            ("1e-9", "1e-9"),
+        ];
+
+        for (input, expected) in tests.iter() {
+            let evaluated = test_eval(input).to_string();
+            assert_eq!(evaluated, *expected, "wrong display. expected={}, got={}", expected, evaluated);
+        }
+    }
+
+    #[test]
+    fn test_eval_boolean_expression() {
+        let tests = [
+            ("true", true),
+            ("false", false),
+            ("1 < 2", true),
+            ("1 > 2", false),
+            ("1 < 1", false),
+            ("1 > 1", false),
+            ("1 == 1", true),
+            ("1 != 1", false),
+            ("1 == 2", false),
+            ("1 != 2", true),
+            ("true == true", true),
+            ("false == false", true),
+            ("true == false", false),
+            ("true != false", true),
+            ("(1 < 2) == true", true),
+            ("(1 > 2) == true", false),
+        ];
+
+        for (input, expected) in tests.iter() {
+            test_boolean_object(&test_eval(input), *expected);
+        }
+    }
+
+    #[test]
+    fn test_string_expressions() {
+        let tests = [
+            ("\"Hello World!\"", "Hello World!"),
+            ("\"Hello\" + \" \" + \"World!\"", "Hello World!"),
+            ("let greet = fn(name) { \"hi \" + name }; greet(\"monkey\")", "hi monkey"),
+        ];
+
+        for (input, expected) in tests.iter() {
+            match test_eval(input) {
+                Object::String(s) => assert_eq!(s, *expected, "String has wrong value. got={:?}", s),
+                obj => panic!("object is not String. got={:?}", obj)
+            }
+        }
+
+        test_boolean_object(&test_eval("\"a\" == \"a\""), true);
+        test_boolean_object(&test_eval("\"a\" != \"a\""), false);
+    }
+
+    #[test]
+    fn test_bang_operator() {
+        let tests = [
+            ("!true", false),
+            ("!false", true),
+            ("!5", false),
+            ("!!true", true),
+            ("!!false", false),
+            ("!!5", true),
+        ];
+
+        for (input, expected) in tests.iter() {
+            test_boolean_object(&test_eval(input), *expected);
+        }
+    }
+
+    #[test]
+    fn test_if_else_expressions() {
+        let tests = [
+            ("if (true) { 10 }", Some(10)),
+            ("if (false) { 10 }", None),
+            ("if (1) { 10 }", Some(10)),
+            ("if (1 < 2) { 10 }", Some(10)),
+            ("if (1 > 2) { 10 }", None),
+            ("if (1 > 2) { 10 } else { 20 }", Some(20)),
+            ("if (1 < 2) { 10 } else { 20 }", Some(10)),
+        ];
+
+        for (input, expected) in tests.iter() {
+            let evaluated = test_eval(input);
+            match expected {
+                Some(i) => test_integer_object(&evaluated, *i),
+                None => test_null_object(&evaluated)
+            }
+        }
+    }
+
+    #[test]
+    fn test_return_statements() {
+        let tests = [
+            ("return 10;", 10),
+            ("return 10; 9;", 10),
+            ("return 2 * 5; 9;", 10),
+            ("9; return 2 * 5; 9;", 10),
+            ("if (10 > 1) { if (10 > 1) { return 10; } return 1; }", 10),
+        ];
+
+        for (input, expected) in tests.iter() {
+            test_integer_object(&test_eval(input), *expected);
+        }
+    }
+
+    #[test]
+    fn test_error_handling() {
+        let tests = [
+            ("5 + true;", "type mismatch: INTEGER + BOOLEAN"),
+            ("5 + true; 5;", "type mismatch: INTEGER + BOOLEAN"),
+            ("-true", "unknown operator: -BOOLEAN"),
+            ("true + false;", "unknown operator: BOOLEAN + BOOLEAN"),
+            ("5; true + false; 5", "unknown operator: BOOLEAN + BOOLEAN"),
+            ("if (10 > 1) { true + false; }", "unknown operator: BOOLEAN + BOOLEAN"),
+            ("if (10 > 1) { if (10 > 1) { return true + false; } return 1; }", "unknown operator: BOOLEAN + BOOLEAN"),
+            ("foobar", "identifier not found: foobar"),
+            ("10 / 0", "division by zero: 10 / 0"),
+            ("5(1)", "not a function: INTEGER"),
+            ("\"Hello\" - \"World\"", "unknown operator: STRING - STRING"),
+            ("fn(x) { x }(1, 2)", "wrong number of arguments: expected 1, got 2"),
+            ("[1, 2][true]", "index operator not supported: ARRAY[BOOLEAN]"),
+            ("5[0]", "index operator not supported: INTEGER[INTEGER]"),
+            ("[1, foo]", "identifier not found: foo"),
+            ("{fn(x) { x }: 1}", "unusable as hash key: FUNCTION"),
+            ("{\"a\": 1}[[1]]", "unusable as hash key: ARRAY"),
+            ("{1: foo}", "identifier not found: foo"),
+        ];
+
+        for (input, expected) in tests.iter() {
+            match test_eval(input) {
+                Object::Error(_, msg, _) => assert_eq!(msg, *expected, "wrong error message. expected={}, got={}", expected, msg),
+                obj => panic!("no error object returned. got={:?}", obj)
+            }
+        }
+    }
+
+    #[test]
+    fn test_let_statements() {
+        let tests = [
+            ("let a = 5; a;", 5),
+            ("let a = 5 * 5; a;", 25),
+            ("let a = 5; let b = a; b;", 5),
+            ("let a = 5; let b = a; let c = a + b + 5; c;", 15),
+        ];
+
+        for (input, expected) in tests.iter() {
+            test_integer_object(&test_eval(input), *expected);
+        }
+    }
+
+    #[test]
+    fn test_function_object() {
+        match test_eval("fn(x) { x + 2; };") {
+            Object::Function { params, body, .. } => {
+                assert_eq!(params.len(), 1, "function has wrong parameters. got={}", params.len());
+                assert_eq!(params[0].value, "x", "parameter is not 'x'. got={}", params[0]);
+                assert_eq!(body.to_string(), "{ (x + 2); }", "body is not '{{ (x + 2); }}'. got={}", body);
+            },
+            obj => panic!("object is not Function. got={:?}", obj)
+        }
+    }
+
+    #[test]
+    fn test_function_application() {
+        let tests = [
+            ("let identity = fn(x) { x; }; identity(5);", 5),
+            ("let identity = fn(x) { return x; }; identity(5);", 5),
+            ("let double = fn(x) { x * 2; }; double(5);", 10),
+            ("let add = fn(x, y) { x + y; }; add(5, 5);", 10),
+            ("let add = fn(x, y) { x + y; }; add(5 + 5, add(5, 5));", 20),
+            ("fn(x) { x; }(5)", 5),
+        ];
+
+        for (input, expected) in tests.iter() {
+            test_integer_object(&test_eval(input), *expected);
+        }
+    }
+
+    #[test]
+    fn test_closures() {
+        let tests = [
+            ("let newAdder = fn(x) { fn(y) { x + y } }; let addTwo = newAdder(2); addTwo(3);", 5),
+            ("let add = fn(a) { fn(b) { fn(c) { a + b + c } } }; add(1)(2)(3);", 6),
+            ("let newAdder = fn(x) { fn(y) { x + y } }; let addTwo = newAdder(2); let addTen = newAdder(10); addTwo(1) + addTen(1);", 14),
+            ("let getX = fn() { x }; let x = 7; getX();", 7),
+        ];
+
+        for (input, expected) in tests.iter() {
+            test_integer_object(&test_eval(input), *expected);
+        }
+    }
+
+    // documents the leak described on Environment, so fixing it shows up here
+    #[test]
+    fn test_function_bound_in_its_scope_leaks_it() {
+        let tests = [
+            ("let f = fn() { f };", true),
+            ("let x = fn(y) { y }(1);", false),
+        ];
+
+        for (input, leaks) in tests.iter() {
+            let program = parse(input).unwrap();
+            let mut env = Environment::new();
+            eval(&program, &mut env);
+
+            let scope = Rc::downgrade(&env.scope);
+            drop(env);
+            assert_eq!(scope.upgrade().is_some(), *leaks, "wrong scope lifetime for '{}'. expected leak={}", input, leaks);
+        }
+    }
+
+    #[test]
+    fn test_recursive_functions() {
+        let tests = [
+            ("let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(15);", 610),
+            ("let countdown = fn(n) { if (n == 0) { 0 } else { countdown(n - 1) } }; countdown(100);", 0),
+            ("let wrapper = fn() { let inner = fn(n) { if (n == 0) { 42 } else { inner(n - 1) } }; inner(5) }; wrapper();", 42),
+        ];
+
+        for (input, expected) in tests.iter() {
+            test_integer_object(&test_eval(input), *expected);
+        }
+    }
+
+    #[test]
+    fn test_error_spans() {
+        let tests = [
+            ("5 + true;", "5 + true"),
+            ("let x = 1;\nlet y = -true;", "-true"),
+            ("if (1 < 2) { foobar }", "foobar"),
+            ("let f = fn(a) { a / 0 }; f(1) + 1", "a / 0"),
+        ];
+
+        for (input, expected) in tests.iter() {
+            match test_eval(input) {
+                Object::Error(_, _, Some(span)) => {
+                    let text = &input[span.start..span.end];
+                    assert_eq!(text, *expected, "error points at the wrong source. expected={}, got={}", expected, text);
+                },
+                obj => panic!("no error object with a span returned. got={:?}", obj)
+            }
+        }
+    }
+}
@@ -0,0 +1,303 @@
+use crate::cst::{self, NodeKind, SyntaxElement, SyntaxNode};
+use crate::token::TokenType;
+
+const INDENT: &str = "    ";
+
+// reprints source in the canonical style: one statement per line, every statement ends
+// with ';', single spaces around binary operators and 4 space indentation. comments are
+// kept where they were, and a blank line between statements survives (but only one).
+// the source should parse cleanly, anything the parser didn't understand is copied as is
+pub fn format(src: &str) -> String {
+    let tree = cst::parse(src);
+
+    let mut printer = Printer {
+        out: String::new(),
+        indent: 0
+    };
+    printer.print_statements(&tree.children_with_tokens());
+
+    if !printer.out.is_empty() {
+        printer.out.push('\n');
+    }
+    printer.out
+}
+
+// what a significant child is, used to decide on the spacing between two of them
+enum Item {
+    Token(TokenType),
+    Node(NodeKind),
+}
+
+impl Item {
+    fn of(element: &SyntaxElement) -> Item {
+        match element {
+            SyntaxElement::Node(node) => Item::Node(node.kind()),
+            SyntaxElement::Token(token) => Item::Token(token.kind().clone()),
+        }
+    }
+
+    fn is_closing(&self) -> bool {
+        matches!(self, Item::Token(TokenType::Semicolon | TokenType::Comma | TokenType::Colon
+            | TokenType::Rparen | TokenType::Rbracket | TokenType::Rbrace))
+    }
+}
+
+fn space_between(parent: NodeKind, prev: &Item, next: &Item) -> bool {
+    match (prev, next) {
+        (_, next) if next.is_closing() => false,
+        // blocks are printed on their own, so a '{' here opens a hash
+        (Item::Token(TokenType::Lparen | TokenType::Lbracket | TokenType::Lbrace), _) => false,
+        (_, Item::Node(NodeKind::ArgList | NodeKind::ParamList)) => false,
+        _ => parent != NodeKind::Prefix && parent != NodeKind::Index
+    }
+}
+
+fn is_line_comment(text: &str) -> bool {
+    text.starts_with("//")
+}
+
+struct Printer {
+    out: String,
+    indent: usize
+}
+
+impl Printer {
+    fn space(&mut self) {
+        if !self.out.is_empty() && !self.out.ends_with(' ') && !self.out.ends_with('\n') {
+            self.out.push(' ');
+        }
+    }
+
+    fn begin_line(&mut self, blank: bool) {
+        if !self.out.is_empty() {
+            self.out.push('\n');
+            if blank {
+                self.out.push('\n');
+            }
+        }
+        self.out.push_str(&INDENT.repeat(self.indent));
+    }
+
+    // the children of the program or a block: statements and the comments around them
+    fn print_statements(&mut self, elements: &[SyntaxElement]) {
+        let mut first = true;
+        let mut newlines = 0;
+
+        for element in elements {
+            match element {
+                SyntaxElement::Token(token) if *token.kind() == TokenType::Whitespace => {
+                    newlines += token.text().matches('\n').count();
+                    continue;
+                },
+                // a comment on the same line as the previous statement stays there
+                SyntaxElement::Token(token) if *token.kind() == TokenType::Comment && !first && newlines == 0 => {
+                    self.space();
+                    self.print_comment(token.text());
+                },
+                SyntaxElement::Token(token) => {
+                    self.begin_line(!first && newlines > 1);
+                    self.print_comment(token.text());
+                },
+                SyntaxElement::Node(node) => {
+                    self.begin_line(!first && newlines > 1);
+                    self.print_statement(node);
+                },
+            }
+
+            first = false;
+            newlines = 0;
+        }
+    }
+
+    fn print_comment(&mut self, text: &str) {
+        if is_line_comment(text) {
+            self.out.push_str(text.trim_end());
+        } else {
+            self.out.push_str(text);
+        }
+    }
+
+    fn print_statement(&mut self, node: &SyntaxNode) {
+        self.print_inline(node);
+
+        let has_semicolon = node.children_with_tokens().iter()
+            .any(|element| matches!(element, SyntaxElement::Token(token) if *token.kind() == TokenType::Semicolon));
+        if node.kind() != NodeKind::Error && !has_semicolon {
+            self.out.push(';');
+        }
+    }
+
+    fn print_element(&mut self, element: &SyntaxElement) {
+        match element {
+            SyntaxElement::Node(node) if node.kind() == NodeKind::Block => self.print_block(node),
+            SyntaxElement::Node(node) => self.print_inline(node),
+            SyntaxElement::Token(token) => self.out.push_str(token.text()),
+        }
+    }
+
+    // a node printed on one line, unless a line comment inside it forces a break
+    fn print_inline(&mut self, node: &SyntaxNode) {
+        let mut prev: Option<Item> = None;
+        let mut after_comment = false;
+        let elements = node.children_with_tokens();
+
+        for (i, element) in elements.iter().enumerate() {
+            if let SyntaxElement::Token(token) = element {
+                match token.kind() {
+                    TokenType::Whitespace => continue,
+                    TokenType::Comment => {
+                        // an 'else' goes back to the indentation of its 'if'
+                        let before_else = elements[i + 1..].iter()
+                            .find(|next| !matches!(next, SyntaxElement::Token(token) if matches!(token.kind(), TokenType::Whitespace | TokenType::Comment)))
+                            .is_some_and(|next| matches!(next, SyntaxElement::Token(token) if *token.kind() == TokenType::Else));
+                        let indent = if before_else { self.indent } else { self.indent + 1 };
+
+                        self.print_inline_comment(token.text(), indent);
+                        after_comment = true;
+                        continue;
+                    },
+                    _ => {}
+                }
+            }
+
+            let item = Item::of(element);
+            let spaced = match &prev {
+                Some(prev) => space_between(node.kind(), prev, &item),
+                None => false
+            };
+            if spaced || (after_comment && !item.is_closing()) {
+                self.space();
+            }
+
+            self.print_element(element);
+            prev = Some(item);
+            after_comment = false;
+        }
+    }
+
+    // a line comment ends the line, what follows continues at the given indentation
+    fn print_inline_comment(&mut self, text: &str, indent: usize) {
+        if is_line_comment(text) {
+            self.space();
+            self.print_comment(text);
+            self.out.push('\n');
+            self.out.push_str(&INDENT.repeat(indent));
+        } else {
+            if !self.out.ends_with('(') && !self.out.ends_with('[') && !self.out.ends_with('{') {
+                self.space();
+            }
+            self.print_comment(text);
+        }
+    }
+
+    fn print_block(&mut self, node: &SyntaxNode) {
+        let elements = node.children_with_tokens();
+        let inner = elements.iter()
+            .filter(|element| !matches!(element, SyntaxElement::Token(token) if matches!(token.kind(), TokenType::Lbrace | TokenType::Rbrace)))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let is_empty = inner.iter().all(|element| matches!(element, SyntaxElement::Token(token) if *token.kind() == TokenType::Whitespace));
+        if is_empty {
+            self.out.push_str("{}");
+            return;
+        }
+
+        self.out.push('{');
+        self.indent += 1;
+        self.print_statements(&inner);
+        self.indent -= 1;
+        self.begin_line(false);
+        self.out.push('}');
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::parse;
+
+    fn check_format(input: &str, expected: &str) {
+        let formatted = format(input);
+        assert_eq!(formatted, expected, "wrong format for {:?}. got=\n{}", input, formatted);
+
+        let again = format(&formatted);
+        assert_eq!(again, formatted, "format is not idempotent. got=\n{}", again);
+
+        // formatting must not change what the program means
+        match (parse(input), parse(&formatted)) {
+            (Ok(before), Ok(after)) => assert_eq!(after.to_string(), before.to_string(), "formatting changed the program"),
+            (before, after) => panic!("parse failed. before={:?}, after={:?}", before.err(), after.err())
+        }
+    }
+
+    #[test]
+    fn test_spacing_and_semicolons() {
+        check_format("let x=1+2*3\nlet y = -x;x!=y", "let x = 1 + 2 * 3;\nlet y = -x;\nx != y;\n");
+        check_format("add( 1,2 , (3+4) )", "add(1, 2, (3 + 4));\n");
+        check_format("let a=[ 1,[2 ,3],[ ] ];a [0]+a[1][ 1 ]", "let a = [1, [2, 3], []];\na[0] + a[1][1];\n");
+        check_format("let h={ \"a\" :1,2:{ },true : [3] };h[\"a\"]", "let h = {\"a\": 1, 2: {}, true: [3]};\nh[\"a\"];\n");
+        check_format("  !true  ;", "!true;\n");
+        check_format("", "");
+    }
+
+    #[test]
+    fn test_blocks_and_indentation() {
+        let input = "let max=fn(a,b){if(a>b){return a;}else{b}};let noop = fn() {   };";
+        let expected = "let max = fn(a, b) {
+    if (a > b) {
+        return a;
+    } else {
+        b;
+    };
+};
+let noop = fn() {};
+";
+        check_format(input, expected);
+    }
+
+    #[test]
+    fn test_comments_are_kept() {
+        let input = "// header
+
+
+/// Adds things.
+let add = fn(a, b) { // body
+  /* sum */ a + b   // the result
+};
+add(1, /* two */ 2); // call
+/* trailing */
+";
+        let expected = "// header
+
+/// Adds things.
+let add = fn(a, b) {
+    // body
+    /* sum */
+    a + b; // the result
+};
+add(1, /* two */ 2); // call
+/* trailing */
+";
+        check_format(input, expected);
+    }
+
+    #[test]
+    fn test_comments_inside_expressions() {
+        check_format("let x = 1 + // why\n2;", "let x = 1 + // why\n    2;\n");
+        check_format("f(/* a */ x)", "f(/* a */ x);\n");
+        check_format("if (a) { b } // c\nelse { c }", "if (a) {\n    b;\n} // c\nelse {\n    c;\n};\n");
+        check_format("if (a) { b } // c\n// d\nelse { c }", "if (a) {\n    b;\n} // c\n// d\nelse {\n    c;\n};\n");
+    }
+
+    #[test]
+    fn test_blank_lines() {
+        check_format("let a = 1;\n\n\n\nlet b = 2;\nlet c = 3;\n\n", "let a = 1;\n\nlet b = 2;\nlet c = 3;\n");
+        check_format("fn() {\n\n  a;\n\n  b;\n\n}", "fn() {\n    a;\n\n    b;\n};\n");
+    }
+
+    #[test]
+    fn test_strings_keep_their_escapes() {
+        check_format("let s=\"a\\tb \\u{1F600}\"", "let s = \"a\\tb \\u{1F600}\";\n");
+    }
+}
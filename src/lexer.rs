@@ -1,13 +1,19 @@
+use unicode_xid::UnicodeXID;
 use crate::token::*;
 
 const ZERO_CHAR:char = 0u8 as char;
 
+// identifiers follow unicode's XID_Start/XID_Continue, plus '_' anywhere
 fn is_letter(ch: char) -> bool {
-    'a' <= ch && ch <= 'z' || 'A' <= ch && ch <= 'Z' || ch == '_'
+    ch == '_' || UnicodeXID::is_xid_start(ch)
+}
+
+fn is_identifier_char(ch: char) -> bool {
+    ch == '_' || UnicodeXID::is_xid_continue(ch)
 }
 
 fn is_digit(ch: char) -> bool {
-    '0' <= ch && ch <= '9'
+    ch.is_ascii_digit()
 }
 
 pub struct Lexer {
@@ -15,6 +21,12 @@ pub struct Lexer {
     position: usize,
     read_position: usize,
     ch: char,
+    line: usize,
+    col: usize,
+    doc: Vec<String>,
+    trivia: bool,
+    // where input starts in the source its spans refer to
+    offset: usize,
 }
 
 impl Lexer {
@@ -23,7 +35,12 @@ impl Lexer {
             input: input.to_string(),
             position: 0,
             read_position: 0,
-            ch: ZERO_CHAR
+            ch: ZERO_CHAR,
+            line: 1,
+            col: 0,
+            doc: vec![],
+            trivia: false,
+            offset: 0
         };
 
         lexer.read_char();
@@ -31,67 +48,213 @@ impl Lexer {
         lexer 
     }
 
+    // lexes input that starts a line at a byte offset of a larger source, with spans into that source.
+    // the repl keeps every input in one session this way, so a span can't point into the wrong input
+    pub fn with_offset(input: &str, offset: usize, line: usize) -> Lexer {
+        let mut lexer = Lexer::new(input);
+        lexer.offset = offset;
+        lexer.line = line;
+        lexer
+    }
+
+    // a lossless lexer returns whitespace and comments as tokens instead of skipping them,
+    // so the tokens' spans cover the whole input
+    pub fn with_trivia(input: &str) -> Lexer {
+        let mut lexer = Lexer::new(input);
+        lexer.trivia = true;
+        lexer
+    }
+
     fn read_char(&mut self) {
-        if self.read_position >= self.input.len() {
-            self.ch = ZERO_CHAR;
+        if self.ch == '\n' {
+            self.line += 1;
+            self.col = 1;
         } else {
-            self.ch = self.input.as_bytes()[self.read_position] as char;
+            self.col += 1;
         }
 
+        // positions are byte offsets, but we always step over a whole char
         self.position = self.read_position;
-        self.read_position += 1;
+        match self.char_at(self.read_position) {
+            Some(ch) => {
+                self.ch = ch;
+                self.read_position += ch.len_utf8();
+            },
+            None => {
+                self.ch = ZERO_CHAR;
+                self.read_position += 1;
+            }
+        }
+    }
+
+    fn char_at(&self, position: usize) -> Option<char> {
+        self.input.get(position..).and_then(|s| s.chars().next())
     }
     
-    fn read_number(&mut self) -> String {
+    // integers in decimal, hex (0xFF), octal (0o755) or binary (0b1010), and decimal floats with
+    // an optional fraction and exponent: 3.14, .5, 1e-9. '_' can separate digits, 1_000_000.
+    // a prefixed literal takes every identifier char after it, so the parser can report
+    // something like '0b102' as a whole
+    fn read_number(&mut self) -> Token {
         let position = self.position;
-        while is_digit(self.ch) {
+
+        if self.ch == '0' && matches!(self.peek_char(), 'x' | 'X' | 'o' | 'O' | 'b' | 'B') {
+            self.read_char();
             self.read_char();
+            while is_identifier_char(self.ch) {
+                self.read_char();
+            }
+            return Token::new(TokenType::Int, &self.input[position..self.position]);
         }
 
+        let mut typ = TokenType::Int;
+        self.read_digits();
 
-        let v = self.input.as_bytes()[position..self.position].iter()
-            .map(|v| *v)
-            .collect::<Vec<_>>();
+        if self.ch == '.' && is_digit(self.peek_char()) {
+            typ = TokenType::Float;
+            self.read_char();
+            self.read_digits();
+        }
+
+        if (self.ch == 'e' || self.ch == 'E') && self.exponent_follows() {
+            typ = TokenType::Float;
+            self.read_char();
+            if self.ch == '+' || self.ch == '-' {
+                self.read_char();
+            }
+            self.read_digits();
+        }
+
+        Token::new(typ, &self.input[position..self.position])
+    }
+
+    fn read_digits(&mut self) {
+        while is_digit(self.ch) || self.ch == '_' {
+            self.read_char();
+        }
+    }
 
-        String::from_utf8(v).unwrap_or("".to_string())
+    // an 'e' only starts an exponent when digits follow, '2else' is still '2' and 'else'
+    fn exponent_follows(&self) -> bool {
+        let rest = self.input.get(self.read_position..).unwrap_or("");
+        let rest = rest.strip_prefix(['+', '-']).unwrap_or(rest);
+        rest.starts_with(|ch: char| ch.is_ascii_digit())
     }
 
     fn read_identifier(&mut self) -> String {
         let position = self.position;
-        while is_letter(self.ch) {
+        while is_identifier_char(self.ch) {
             self.read_char();
         }
-        
-        let v = self.input.as_bytes()[position..self.position].iter()
-            .map(|v| *v)
-            .collect::<Vec<_>>();
 
-        String::from_utf8(v).unwrap_or("".to_string())
+        self.input[position..self.position].to_string()
     }
 
-    fn peek_char(&self) -> char {
-        if self.read_position >= self.input.len() {
-            ZERO_CHAR
-        } else {
-            self.input.as_bytes()[self.read_position] as char
+    // reads a double quoted string and returns its unescaped value. an unterminated string
+    // runs to the end of the input, and the first bad escape is reported in the literal
+    fn read_string(&mut self) -> Token {
+        let mut value = String::new();
+        let mut invalid_escape = None;
+
+        loop {
+            self.read_char();
+            match self.ch {
+                '"' => break,
+                ZERO_CHAR => return Token::new(TokenType::UnterminatedString, &value),
+                '\\' => {
+                    let escape_start = self.position;
+                    self.read_char();
+
+                    let escaped = match self.ch {
+                        'n' => Some('\n'),
+                        't' => Some('\t'),
+                        '\\' => Some('\\'),
+                        '"' => Some('"'),
+                        'u' => self.read_unicode_escape(),
+                        ZERO_CHAR => return Token::new(TokenType::UnterminatedString, &value),
+                        _ => None
+                    };
+
+                    match escaped {
+                        Some(ch) => value.push(ch),
+                        None => {
+                            let end = self.read_position.min(self.input.len());
+                            invalid_escape.get_or_insert_with(|| self.input[escape_start..end].to_string());
+                        }
+                    }
+                },
+                ch => value.push(ch)
+            }
         }
+
+        match invalid_escape {
+            Some(escape) => Token::new(TokenType::InvalidEscape, &escape),
+            None => Token::new(TokenType::String, &value)
+        }
+    }
+
+    // the part after '\u', which must be '{' followed by 1 to 6 hex digits and '}'.
+    // stops before any unexpected char so a closing quote is never swallowed
+    fn read_unicode_escape(&mut self) -> Option<char> {
+        if self.peek_char() != '{' {
+            return None;
+        }
+        self.read_char();
+
+        let mut digits = String::new();
+        while self.peek_char().is_ascii_hexdigit() {
+            self.read_char();
+            digits.push(self.ch);
+        }
+
+        if self.peek_char() != '}' {
+            return None;
+        }
+        self.read_char();
+
+        if digits.is_empty() || digits.len() > 6 {
+            return None;
+        }
+
+        u32::from_str_radix(&digits, 16).ok().and_then(std::char::from_u32)
+    }
+
+    fn peek_char(&self) -> char {
+        self.char_at(self.read_position).unwrap_or(ZERO_CHAR)
+    }
+
+    fn span(&self, start: usize, end: usize, line: usize, col: usize) -> Span {
+        Span::new(self.offset + start, self.offset + end, line, col)
     }
 
     pub fn next_token(&mut self) -> Token {
-        self.skip_whitespace();
-        
+        if self.trivia {
+            if let Some(token) = self.read_trivia() {
+                return token;
+            }
+        } else if let Some(token) = self.skip_whitespace() {
+            return token;
+        }
+
+        let start = self.position.min(self.input.len());
+        let (line, col) = (self.line, self.col);
+
+        let mut token = self.read_token();
+        token.span = self.span(start, self.position.min(self.input.len()), line, col);
+        if !self.doc.is_empty() {
+            token.doc = Some(self.doc.join("\n"));
+            self.doc.clear();
+        }
+        token
+    }
+
+    fn read_token(&mut self) -> Token {
         let token = match self.ch {
             '=' => {
                 if self.peek_char() == '=' {
                     let ch = self.ch;
                     self.read_char();
-                    let literal = format!(
-                        "{}{}",
-                        String::from_utf8(vec![ch as u8]).unwrap_or("".to_string()),
-                        String::from_utf8(vec![self.ch as u8]).unwrap_or("".to_string()),
-                    );
-
-                    Token::new(TokenType::Equal, &literal)
+                    Token::new(TokenType::Equal, &format!("{}{}", ch, self.ch))
                 } else {
                     Token::from_char(TokenType::Assign, self.ch)
                 }
@@ -102,13 +265,7 @@ impl Lexer {
                 if self.peek_char() == '=' {
                     let ch = self.ch;
                     self.read_char();
-                    let literal = format!(
-                        "{}{}",
-                        String::from_utf8(vec![ch as u8]).unwrap_or("".to_string()),
-                        String::from_utf8(vec![self.ch as u8]).unwrap_or("".to_string()),
-                    );
-
-                    Token::new(TokenType::NotEqual, &literal)
+                    Token::new(TokenType::NotEqual, &format!("{}{}", ch, self.ch))
                 } else {
                     Token::from_char(TokenType::Bang, self.ch)
                 }
@@ -118,18 +275,23 @@ impl Lexer {
             '<' => Token::from_char(TokenType::LT, self.ch),
             '>' => Token::from_char(TokenType::GT, self.ch),
             ';' => Token::from_char(TokenType::Semicolon, self.ch),
+            ':' => Token::from_char(TokenType::Colon, self.ch),
             '(' => Token::from_char(TokenType::Lparen, self.ch),
             ')' => Token::from_char(TokenType::Rparen, self.ch),
             ',' => Token::from_char(TokenType::Comma, self.ch),
             '{' => Token::from_char(TokenType::Lbrace, self.ch),
             '}' => Token::from_char(TokenType::Rbrace, self.ch),
+            '[' => Token::from_char(TokenType::Lbracket, self.ch),
+            ']' => Token::from_char(TokenType::Rbracket, self.ch),
+            '"' => self.read_string(),
+            '.' if is_digit(self.peek_char()) => return self.read_number(),
             ZERO_CHAR => Token::new(TokenType::EOF, ""),
             _ => {
                 if is_letter(self.ch) {
                     let literal = self.read_identifier();
                     return Token::new(lookup_ident(&literal), &literal);
                 } else if is_digit(self.ch) {
-                    return Token::new(TokenType::Int, &self.read_number());
+                    return self.read_number();
                 } else {
                     Token::from_char(TokenType::Illegal, self.ch)
                 }
@@ -140,8 +302,93 @@ impl Lexer {
         token
     }
 
-    fn skip_whitespace(&mut self) {
-        while self.ch == ' ' || self.ch == '\t' || self.ch == '\n' || self.ch == '\r' {
+    // skips whitespace and comments, an unterminated block comment comes back as an error token
+    fn skip_whitespace(&mut self) -> Option<Token> {
+        loop {
+            match self.ch {
+                ' ' | '\t' | '\n' | '\r' => self.read_char(),
+                '/' if self.peek_char() == '/' => self.skip_line_comment(),
+                '/' if self.peek_char() == '*' => {
+                    if let Some(token) = self.skip_block_comment() {
+                        return Some(token);
+                    }
+                },
+                _ => return None
+            }
+        }
+    }
+
+    // a run of whitespace or a single comment, with the source text as literal
+    fn read_trivia(&mut self) -> Option<Token> {
+        let (start, line, col) = (self.position, self.line, self.col);
+
+        let typ = match self.ch {
+            ' ' | '\t' | '\n' | '\r' => {
+                while matches!(self.ch, ' ' | '\t' | '\n' | '\r') {
+                    self.read_char();
+                }
+                TokenType::Whitespace
+            },
+            '/' if self.peek_char() == '/' => {
+                self.skip_line_comment();
+                TokenType::Comment
+            },
+            '/' if self.peek_char() == '*' => {
+                if let Some(token) = self.skip_block_comment() {
+                    return Some(token);
+                }
+                TokenType::Comment
+            },
+            _ => return None
+        };
+
+        let end = self.position.min(self.input.len());
+        let mut token = Token::new(typ, &self.input[start..end]);
+        token.span = self.span(start, end, line, col);
+        Some(token)
+    }
+
+    // '///' starts a doc comment (but '////' doesn't), its text is kept for the next token
+    fn skip_line_comment(&mut self) {
+        let start = self.position;
+        while self.ch != '\n' && self.ch != ZERO_CHAR {
+            self.read_char();
+        }
+
+        let comment = &self.input[start..self.position];
+        if comment.starts_with("///") && !comment.starts_with("////") {
+            let text = comment[3..].trim_end();
+            self.doc.push(text.strip_prefix(' ').unwrap_or(text).to_string());
+        }
+    }
+
+    // block comments nest, so every '/*' needs its own '*/'
+    fn skip_block_comment(&mut self) -> Option<Token> {
+        let (start, line, col) = (self.position, self.line, self.col);
+        let mut depth = 0;
+
+        loop {
+            match (self.ch, self.peek_char()) {
+                ('/', '*') => {
+                    depth += 1;
+                    self.read_char();
+                },
+                ('*', '/') => {
+                    depth -= 1;
+                    self.read_char();
+                    if depth == 0 {
+                        self.read_char();
+                        return None;
+                    }
+                },
+                (ZERO_CHAR, _) => {
+                    let mut token = Token::new(TokenType::UnterminatedComment, "/*");
+                    token.span = self.span(start, self.input.len(), line, col);
+                    return Some(token);
+                },
+                _ => {}
+            }
+
             self.read_char();
         }
     }
@@ -151,6 +398,286 @@ impl Lexer {
 mod test {
     use super::*;
 
+    #[test]
+    fn test_token_spans() {
+        let input = "let x = 10;\n  x != y\n";
+        let tests = vec![
+            (TokenType::Let, Span::new(0, 3, 1, 1)),
+            (TokenType::Ident, Span::new(4, 5, 1, 5)),
+            (TokenType::Assign, Span::new(6, 7, 1, 7)),
+            (TokenType::Int, Span::new(8, 10, 1, 9)),
+            (TokenType::Semicolon, Span::new(10, 11, 1, 11)),
+            (TokenType::Ident, Span::new(14, 15, 2, 3)),
+            (TokenType::NotEqual, Span::new(16, 18, 2, 5)),
+            (TokenType::Ident, Span::new(19, 20, 2, 8)),
+            (TokenType::EOF, Span::new(21, 21, 3, 1)),
+        ];
+
+        let mut lexer = Lexer::new(input);
+        for (i, (e_tok, e_span)) in tests.iter().enumerate() {
+            let tok = lexer.next_token();
+            assert_eq!(tok.typ, *e_tok, "Wrong tokentype. {}: expected={:?}, got={:?}", i, e_tok, tok.typ);
+            assert_eq!(tok.span, *e_span, "Wrong span. {}: expected={:?}, got={:?}", i, e_span, tok.span);
+        }
+    }
+
+    #[test]
+    fn test_numbers() {
+        let input = "5 3.14 .5 1e-9 2.5E+3 10e2 7. x.5 2else 3e";
+        let tests = vec![
+            (TokenType::Int, "5"),
+            (TokenType::Float, "3.14"),
+            (TokenType::Float, ".5"),
+            (TokenType::Float, "1e-9"),
+            (TokenType::Float, "2.5E+3"),
+            (TokenType::Float, "10e2"),
+            (TokenType::Int, "7"),
+            (TokenType::Illegal, "."),
+            (TokenType::Ident, "x"),
+            (TokenType::Float, ".5"),
+            (TokenType::Int, "2"),
+            (TokenType::Else, "else"),
+            (TokenType::Int, "3"),
+            (TokenType::Ident, "e"),
+            (TokenType::EOF, ""),
+        ];
+
+        let mut lexer = Lexer::new(input);
+        for (i, (e_tok, e_lit)) in tests.iter().enumerate() {
+            let tok = lexer.next_token();
+            assert_eq!(tok.typ, *e_tok, "Wrong tokentype. {}: expected={:?}, got={:?}", i, e_tok, tok.typ);
+            assert_eq!(tok.literal, *e_lit, "Wrong literal. {}: expected={}, got={}", i, e_lit, tok.literal);
+        }
+    }
+
+    #[test]
+    fn test_prefixed_and_separated_numbers() {
+        let input = "0xFF 0o755 0b1010 1_000_000 0X_ff 0b102 0x 0xFFg 1_000.000_1 0.5";
+        let tests = vec![
+            (TokenType::Int, "0xFF"),
+            (TokenType::Int, "0o755"),
+            (TokenType::Int, "0b1010"),
+            (TokenType::Int, "1_000_000"),
+            (TokenType::Int, "0X_ff"),
+            (TokenType::Int, "0b102"),
+            (TokenType::Int, "0x"),
+            (TokenType::Int, "0xFFg"),
+            (TokenType::Float, "1_000.000_1"),
+            (TokenType::Float, "0.5"),
+            (TokenType::EOF, ""),
+        ];
+
+        let mut lexer = Lexer::new(input);
+        for (i, (e_tok, e_lit)) in tests.iter().enumerate() {
+            let tok = lexer.next_token();
+            assert_eq!(tok.typ, *e_tok, "Wrong tokentype. {}: expected={:?}, got={:?}", i, e_tok, tok.typ);
+            assert_eq!(tok.literal, *e_lit, "Wrong literal. {}: expected={}, got={}", i, e_lit, tok.literal);
+        }
+    }
+
+    #[test]
+    fn test_string_literals() {
+        let input = r#"
+            "foobar"
+            "foo bar"
+            "line\nbreak\ttab"
+            "quote \" and \\ backslash"
+            "\u{48}\u{1F600}"
+            ""
+            "bad \q escape" "bad \u{110000}" "bad \u{12" 5
+            "never closed
+        "#;
+
+        let tests = vec![
+            (TokenType::String, "foobar"),
+            (TokenType::String, "foo bar"),
+            (TokenType::String, "line\nbreak\ttab"),
+            (TokenType::String, "quote \" and \\ backslash"),
+            (TokenType::String, "H\u{1F600}"),
+            (TokenType::String, ""),
+            (TokenType::InvalidEscape, "\\q"),
+            (TokenType::InvalidEscape, "\\u{110000}"),
+            (TokenType::InvalidEscape, "\\u{12"),
+            (TokenType::Int, "5"),
+            (TokenType::UnterminatedString, "never closed\n        "),
+            (TokenType::EOF, ""),
+        ];
+
+        let mut lexer = Lexer::new(input);
+        for (i, (e_tok, e_lit)) in tests.iter().enumerate() {
+            let tok = lexer.next_token();
+            assert_eq!(tok.typ, *e_tok, "Wrong tokentype. {}: expected={:?}, got={:?}", i, e_tok, tok.typ);
+            assert_eq!(tok.literal, *e_lit, "Wrong literal. {}: expected={}, got={}", i, e_lit, tok.literal);
+        }
+    }
+
+    #[test]
+    fn test_unicode_source() {
+        let input = "let café = \"¿qué tal?\";\nlet 変数 = \"こんにちは\" != x2;\n😀";
+        let tests = vec![
+            (TokenType::Let, "let", Span::new(0, 3, 1, 1)),
+            (TokenType::Ident, "café", Span::new(4, 9, 1, 5)),
+            (TokenType::Assign, "=", Span::new(10, 11, 1, 10)),
+            (TokenType::String, "¿qué tal?", Span::new(12, 25, 1, 12)),
+            (TokenType::Semicolon, ";", Span::new(25, 26, 1, 23)),
+            (TokenType::Let, "let", Span::new(27, 30, 2, 1)),
+            (TokenType::Ident, "変数", Span::new(31, 37, 2, 5)),
+            (TokenType::Assign, "=", Span::new(38, 39, 2, 8)),
+            (TokenType::String, "こんにちは", Span::new(40, 57, 2, 10)),
+            (TokenType::NotEqual, "!=", Span::new(58, 60, 2, 18)),
+            (TokenType::Ident, "x2", Span::new(61, 63, 2, 21)),
+            (TokenType::Semicolon, ";", Span::new(63, 64, 2, 23)),
+            (TokenType::Illegal, "😀", Span::new(65, 69, 3, 1)),
+            (TokenType::EOF, "", Span::new(69, 69, 3, 2)),
+        ];
+
+        let mut lexer = Lexer::new(input);
+        for (i, (e_tok, e_lit, e_span)) in tests.iter().enumerate() {
+            let tok = lexer.next_token();
+            assert_eq!(tok.typ, *e_tok, "Wrong tokentype. {}: expected={:?}, got={:?}", i, e_tok, tok.typ);
+            assert_eq!(tok.literal, *e_lit, "Wrong literal. {}: expected={}, got={}", i, e_lit, tok.literal);
+            assert_eq!(tok.span, *e_span, "Wrong span. {}: expected={:?}, got={:?}", i, e_span, tok.span);
+        }
+    }
+
+    #[test]
+    fn test_comments() {
+        let input = r#"
+            // a line comment
+            let x = 5; // trailing
+            /* block */ let y = /* inline */ x / 2;
+            /* outer /* nested */ still a comment */ y
+            /**/ "// not a comment" //
+        "#;
+
+        let tests = vec![
+            (TokenType::Let, "let"),
+            (TokenType::Ident, "x"),
+            (TokenType::Assign, "="),
+            (TokenType::Int, "5"),
+            (TokenType::Semicolon, ";"),
+            (TokenType::Let, "let"),
+            (TokenType::Ident, "y"),
+            (TokenType::Assign, "="),
+            (TokenType::Ident, "x"),
+            (TokenType::Slash, "/"),
+            (TokenType::Int, "2"),
+            (TokenType::Semicolon, ";"),
+            (TokenType::Ident, "y"),
+            (TokenType::String, "// not a comment"),
+            (TokenType::EOF, ""),
+        ];
+
+        let mut lexer = Lexer::new(input);
+        for (i, (e_tok, e_lit)) in tests.iter().enumerate() {
+            let tok = lexer.next_token();
+            assert_eq!(tok.typ, *e_tok, "Wrong tokentype. {}: expected={:?}, got={:?}", i, e_tok, tok.typ);
+            assert_eq!(tok.literal, *e_lit, "Wrong literal. {}: expected={}, got={}", i, e_lit, tok.literal);
+        }
+    }
+
+    #[test]
+    fn test_trivia_tokens() {
+        let input = "let x = 5; // five\n\t/* a /* nested */ one */x";
+        let tests = vec![
+            (TokenType::Let, "let"),
+            (TokenType::Whitespace, " "),
+            (TokenType::Ident, "x"),
+            (TokenType::Whitespace, " "),
+            (TokenType::Assign, "="),
+            (TokenType::Whitespace, " "),
+            (TokenType::Int, "5"),
+            (TokenType::Semicolon, ";"),
+            (TokenType::Whitespace, " "),
+            (TokenType::Comment, "// five"),
+            (TokenType::Whitespace, "\n\t"),
+            (TokenType::Comment, "/* a /* nested */ one */"),
+            (TokenType::Ident, "x"),
+            (TokenType::EOF, ""),
+        ];
+
+        let mut lexer = Lexer::with_trivia(input);
+        let mut end = 0;
+        for (i, (e_tok, e_lit)) in tests.iter().enumerate() {
+            let tok = lexer.next_token();
+            assert_eq!(tok.typ, *e_tok, "Wrong tokentype. {}: expected={:?}, got={:?}", i, e_tok, tok.typ);
+            assert_eq!(tok.literal, *e_lit, "Wrong literal. {}: expected={:?}, got={:?}", i, e_lit, tok.literal);
+            assert_eq!(tok.span.start, end, "Tokens are not contiguous. {}: expected start={}, got={}", i, end, tok.span.start);
+            end = tok.span.end;
+        }
+    }
+
+    #[test]
+    fn test_doc_comments() {
+        let input = r#"
+            /// Adds two numbers.
+            ///
+            ///   returns x + y
+            let add = fn(x, y) { x + y };
+            //// not a doc comment
+            // neither is this
+            let z = 1; /// belongs to the next let
+            let w = 2;
+        "#;
+
+        let mut lexer = Lexer::new(input);
+        let mut docs = vec![];
+        loop {
+            let tok = lexer.next_token();
+            if tok.typ == TokenType::EOF {
+                break;
+            }
+            if let Some(doc) = tok.doc {
+                docs.push((tok.literal, doc));
+            }
+        }
+
+        let expected = vec![
+            ("let".to_string(), "Adds two numbers.\n\n  returns x + y".to_string()),
+            ("let".to_string(), "belongs to the next let".to_string()),
+        ];
+        assert_eq!(docs, expected, "Wrong doc comments. got={:?}", docs);
+    }
+
+    #[test]
+    fn test_unterminated_block_comment() {
+        let input = "let x = 1;\n  /* outer /* inner */ never closed";
+        let mut lexer = Lexer::new(input);
+
+        for _ in 0..5 {
+            lexer.next_token();
+        }
+
+        let tok = lexer.next_token();
+        assert_eq!(tok.typ, TokenType::UnterminatedComment, "Wrong tokentype. got={:?}", tok.typ);
+        assert_eq!(tok.span, Span::new(13, input.len(), 2, 3), "Wrong span. got={:?}", tok.span);
+        assert_eq!(lexer.next_token().typ, TokenType::EOF, "Expected EOF after the comment");
+    }
+
+    #[test]
+    fn test_offset_spans() {
+        // the second line of "let a = 1;\nlet b = a;\n"
+        let mut l = Lexer::with_offset("let b = a;\n", 11, 2);
+        let tok = l.next_token();
+        assert_eq!(tok.span, Span::new(11, 14, 2, 1), "wrong span. got={:?}", tok.span);
+
+        let tok = l.next_token();
+        assert_eq!(tok.literal, "b", "wrong second token. got={:?}", tok);
+        assert_eq!(tok.span, Span::new(15, 16, 2, 5), "wrong span. got={:?}", tok.span);
+    }
+
+    #[test]
+    fn test_string_spans() {
+        let input = "\"a\\\"b\" x";
+        let mut lexer = Lexer::new(input);
+
+        let tok = lexer.next_token();
+        assert_eq!(tok.span, Span::new(0, 6, 1, 1), "Wrong span for string. got={:?}", tok.span);
+
+        let tok = lexer.next_token();
+        assert_eq!(tok.span, Span::new(7, 8, 1, 8), "Wrong span after string. got={:?}", tok.span);
+    }
+
     #[test]
     fn test_next_token() {
         let input = "=+(){},;";
@@ -247,7 +774,7 @@ mod test {
             };
 
             let result = add(five, ten);
-            !-/*5;
+            !-/ *5;
             5 < 10 > 5;
         "#;
 
@@ -322,7 +849,7 @@ mod test {
             };
 
             let result = add(five, ten);
-            !-/*5;
+            !-/ *5;
             5 < 10 > 5;
 
             if (5 < 10) {
@@ -419,4 +946,53 @@ mod test {
             assert_eq!(tok.literal, *e_lit, "Wrong literal. {}: expected={}, got={}", i, e_lit, tok.literal);
         }
     }
+
+    #[test]
+    fn test_brackets() {
+        let input = "[1, [2]][0]";
+        let tests = vec![
+            (TokenType::Lbracket, "["),
+            (TokenType::Int, "1"),
+            (TokenType::Comma, ","),
+            (TokenType::Lbracket, "["),
+            (TokenType::Int, "2"),
+            (TokenType::Rbracket, "]"),
+            (TokenType::Rbracket, "]"),
+            (TokenType::Lbracket, "["),
+            (TokenType::Int, "0"),
+            (TokenType::Rbracket, "]"),
+            (TokenType::EOF, ""),
+        ];
+
+        let mut lexer = Lexer::new(input);
+        for (i, (e_tok, e_lit)) in tests.iter().enumerate() {
+            let tok = lexer.next_token();
+            assert_eq!(tok.typ, *e_tok, "Wrong tokentype. {}: expected={:?}, got={:?}", i, e_tok, tok.typ);
+            assert_eq!(tok.literal, *e_lit, "Wrong literal. {}: expected={}, got={}", i, e_lit, tok.literal);
+        }
+    }
+
+    #[test]
+    fn test_hash_literal_tokens() {
+        let input = "{\"foo\": \"bar\", 1:true}";
+        let tests = vec![
+            (TokenType::Lbrace, "{"),
+            (TokenType::String, "foo"),
+            (TokenType::Colon, ":"),
+            (TokenType::String, "bar"),
+            (TokenType::Comma, ","),
+            (TokenType::Int, "1"),
+            (TokenType::Colon, ":"),
+            (TokenType::True, "true"),
+            (TokenType::Rbrace, "}"),
+            (TokenType::EOF, ""),
+        ];
+
+        let mut lexer = Lexer::new(input);
+        for (i, (e_tok, e_lit)) in tests.iter().enumerate() {
+            let tok = lexer.next_token();
+            assert_eq!(tok.typ, *e_tok, "Wrong tokentype. {}: expected={:?}, got={:?}", i, e_tok, tok.typ);
+            assert_eq!(tok.literal, *e_lit, "Wrong literal. {}: expected={}, got={}", i, e_lit, tok.literal);
+        }
+    }
 }
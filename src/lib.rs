@@ -0,0 +1,11 @@
+pub mod token;
+pub mod lexer;
+pub mod ast;
+pub mod parser;
+pub mod evaluator;
+pub mod builtins;
+pub mod diagnostic;
+pub mod repl;
+pub mod cst;
+pub mod formatter;
+pub mod doc;
@@ -1,10 +1,223 @@
-mod token;
-mod lexer;
-mod ast;
-mod parser;
+use std::io::{IsTerminal, Read, Write};
+use monkey::{ast, doc, evaluator, formatter, lexer, parser, repl};
+use monkey::evaluator::{Environment, Object, OverflowPolicy};
+use monkey::diagnostic::Diagnostic;
 
-//continue 53
+const USAGE: &str = "usage: monkey [--color=<auto|always|never>] [--error-format=<human|json>] [--overflow=<checked|wrapping|promote>] [run <file | -> | doc [--format=<markdown|html>] <file | -> | fmt [--check] <file | ->]";
+
+#[derive(Clone, Copy, PartialEq)]
+enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    fn enabled(self, is_terminal: bool) -> bool {
+        match self {
+            ColorChoice::Auto => is_terminal && std::env::var_os("NO_COLOR").is_none(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ErrorFormat {
+    Human,
+    Json,
+}
+
+#[derive(Clone, Copy)]
+struct Options {
+    color: ColorChoice,
+    error_format: ErrorFormat,
+    overflow: OverflowPolicy
+}
 
 fn main() {
+    let mut options = Options {
+        color: ColorChoice::Auto,
+        error_format: ErrorFormat::Human,
+        overflow: OverflowPolicy::Checked
+    };
+    let mut args = vec![];
+
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--color=auto" => options.color = ColorChoice::Auto,
+            "--color=always" => options.color = ColorChoice::Always,
+            "--color=never" => options.color = ColorChoice::Never,
+            "--error-format=human" => options.error_format = ErrorFormat::Human,
+            "--error-format=json" => options.error_format = ErrorFormat::Json,
+            "--overflow=checked" => options.overflow = OverflowPolicy::Checked,
+            "--overflow=wrapping" => options.overflow = OverflowPolicy::Wrapping,
+            "--overflow=promote" => options.overflow = OverflowPolicy::Promote,
+            _ => args.push(arg),
+        }
+    }
+
+    // every call recurses through the evaluator, so programs run on a thread with a bigger stack
+    let interpreter = std::thread::Builder::new()
+        .stack_size(evaluator::STACK_SIZE)
+        .spawn(move || dispatch(&args, options));
+    let code = match interpreter {
+        // a panic has already been reported by the thread
+        Ok(handle) => handle.join().unwrap_or(101),
+        Err(e) => {
+            eprintln!("monkey: could not start the interpreter: {}", e);
+            1
+        }
+    };
+
+    std::process::exit(code);
+}
+
+fn dispatch(args: &[String], options: Options) -> i32 {
+    let args = args.iter().map(|a| a.as_str()).collect::<Vec<_>>();
+
+    match args.as_slice() {
+        [] => start_repl(options),
+        ["run", path] => run(path, options),
+        ["doc", path] => doc(path, doc::DocFormat::Markdown, options),
+        ["doc", "--format=markdown", path] => doc(path, doc::DocFormat::Markdown, options),
+        ["doc", "--format=html", path] => doc(path, doc::DocFormat::Html, options),
+        ["fmt", path] => fmt(path, false, options),
+        ["fmt", "--check", path] => fmt(path, true, options),
+        _ => {
+            eprintln!("{}", USAGE);
+            2
+        }
+    }
+}
+
+// the repl prints errors for a person at the prompt, there is no json form of it
+fn start_repl(options: Options) -> i32 {
+    if options.error_format == ErrorFormat::Json {
+        eprintln!("monkey: --error-format=json is not supported by the repl");
+        return 2;
+    }
+
+    let stdin = std::io::stdin();
+    let stdout = std::io::stdout();
+    let color = options.color.enabled(stdout.is_terminal());
+
+    match repl::start(stdin.lock(), stdout.lock(), color, options.overflow) {
+        Ok(_) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+fn read_source(path: &str) -> std::io::Result<String> {
+    if path == "-" {
+        let mut src = String::new();
+        std::io::stdin().read_to_string(&mut src)?;
+        Ok(src)
+    } else {
+        std::fs::read_to_string(path)
+    }
+}
+
+// writes diagnostics to stderr in the requested format
+fn report(diagnostics: &[Diagnostic], file_name: &str, src: &str, options: Options) {
+    let color = options.color.enabled(std::io::stderr().is_terminal());
+
+    for diagnostic in diagnostics {
+        match options.error_format {
+            ErrorFormat::Human => eprint!("{}", diagnostic.render(file_name, src, color)),
+            ErrorFormat::Json => eprintln!("{}", diagnostic.to_json(file_name)),
+        }
+    }
+}
+
+fn display_name(path: &str) -> &str {
+    if path == "-" { "<stdin>" } else { path }
+}
+
+// reads and parses a file, reporting any failure, so commands only deal with valid programs
+fn parse_file(path: &str, options: Options) -> Option<(String, ast::Program)> {
+    let src = match read_source(path) {
+        Ok(src) => src,
+        Err(e) => {
+            report(&[Diagnostic::read_error(path, &e)], display_name(path), "", options);
+            return None;
+        }
+    };
+
+    // big literals are only useful when the evaluator can hold them
+    let mut parser = parser::Parser::new(lexer::Lexer::new(&src)).with_big_integers(options.overflow == OverflowPolicy::Promote);
+    match parser.parse_program() {
+        Ok(program) => Some((src, program)),
+        Err(errors) => {
+            let diagnostics = errors.iter().map(Diagnostic::from_parse_error).collect::<Vec<_>>();
+            report(&diagnostics, display_name(path), &src, options);
+            None
+        }
+    }
+}
+
+fn run(path: &str, options: Options) -> i32 {
+    let (src, program) = match parse_file(path, options) {
+        Some(parsed) => parsed,
+        None => return 1
+    };
+
+    let mut env = Environment::with_overflow_policy(options.overflow);
+    match evaluator::eval(&program, &mut env) {
+        Object::Null => 0,
+        Object::Error(kind, msg, span) => {
+            report(&[Diagnostic::runtime_error(kind, &msg, span)], display_name(path), &src, options);
+            1
+        },
+        obj => match writeln!(std::io::stdout(), "{}", obj) {
+            Ok(_) => 0,
+            Err(e) => {
+                report(&[Diagnostic::write_error("output", &e)], display_name(path), &src, options);
+                1
+            }
+        }
+    }
+}
+
+fn doc(path: &str, format: doc::DocFormat, options: Options) -> i32 {
+    let (_, program) = match parse_file(path, options) {
+        Some(parsed) => parsed,
+        None => return 1
+    };
+
+    print!("{}", doc::generate(display_name(path), &program, format));
+    0
+}
+
+// rewrites the file in place, or prints to stdout for '-'. with check nothing is written
+// and the exit code tells whether the file was already formatted
+fn fmt(path: &str, check: bool, options: Options) -> i32 {
+    let (src, _) = match parse_file(path, options) {
+        Some(parsed) => parsed,
+        None => return 1
+    };
+
+    let formatted = formatter::format(&src);
+
+    if check {
+        if formatted == src {
+            return 0;
+        }
+        report(&[Diagnostic::not_formatted(display_name(path))], display_name(path), &src, options);
+        return 1;
+    }
+
+    if path == "-" {
+        print!("{}", formatted);
+    } else if formatted != src {
+        if let Err(e) = std::fs::write(path, &formatted) {
+            report(&[Diagnostic::write_error(&format!("'{}'", path), &e)], display_name(path), &src, options);
+            return 1;
+        }
+    }
 
+    0
 }
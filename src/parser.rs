@@ -1,14 +1,168 @@
+use num_bigint::BigInt;
 use crate::token::*;
 use crate::lexer::Lexer;
 use crate::ast;
 
-struct Parser {
+#[derive(PartialEq, PartialOrd, Debug, Clone, Copy)]
+pub(crate) enum Precedence {
+    Lowest,
+    Equals,      // ==
+    LessGreater, // > or <
+    Sum,         // +
+    Product,     // *
+    Prefix,      // -X or !X
+    Call,        // my_function(X)
+    Index,       // array[index]
+}
+
+pub fn radix_name(radix: u32) -> &'static str {
+    match radix {
+        2 => "binary",
+        8 => "octal",
+        16 => "hexadecimal",
+        _ => "decimal"
+    }
+}
+
+pub(crate) fn token_precedence(typ: &TokenType) -> Precedence {
+    match typ {
+        TokenType::Equal | TokenType::NotEqual => Precedence::Equals,
+        TokenType::LT | TokenType::GT => Precedence::LessGreater,
+        TokenType::Plus | TokenType::Minus => Precedence::Sum,
+        TokenType::Slash | TokenType::Asterisk => Precedence::Product,
+        TokenType::Lparen => Precedence::Call,
+        TokenType::Lbracket => Precedence::Index,
+        _ => Precedence::Lowest
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParseError {
+    UnexpectedToken {
+        expected: TokenType,
+        found: TokenType,
+        span: Span
+    },
+    NoPrefixParseFn {
+        found: TokenType,
+        span: Span
+    },
+    InvalidIntegerLiteral {
+        literal: String,
+        span: Span
+    },
+    UnterminatedBlock {
+        span: Span
+    },
+    UnterminatedString {
+        span: Span
+    },
+    InvalidEscape {
+        escape: String,
+        span: Span
+    },
+    UnterminatedComment {
+        span: Span
+    },
+    InvalidFloatLiteral {
+        literal: String,
+        span: Span
+    },
+    MissingDigits {
+        literal: String,
+        span: Span
+    },
+    // the span only covers the bad digit
+    InvalidDigit {
+        literal: String,
+        digit: char,
+        radix: u32,
+        span: Span
+    },
+}
+
+impl ParseError {
+    // stable identifiers for tools, see Diagnostic::to_json
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParseError::UnexpectedToken { .. } => "E0001",
+            ParseError::NoPrefixParseFn { .. } => "E0002",
+            ParseError::InvalidIntegerLiteral { .. } => "E0003",
+            ParseError::UnterminatedBlock { .. } => "E0004",
+            ParseError::UnterminatedString { .. } => "E0005",
+            ParseError::InvalidEscape { .. } => "E0006",
+            ParseError::UnterminatedComment { .. } => "E0007",
+            ParseError::InvalidFloatLiteral { .. } => "E0008",
+            ParseError::MissingDigits { .. } => "E0009",
+            ParseError::InvalidDigit { .. } => "E0010",
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::UnexpectedToken { span, .. }
+            | ParseError::NoPrefixParseFn { span, .. }
+            | ParseError::InvalidIntegerLiteral { span, .. }
+            | ParseError::UnterminatedBlock { span }
+            | ParseError::UnterminatedString { span }
+            | ParseError::InvalidEscape { span, .. }
+            | ParseError::UnterminatedComment { span }
+            | ParseError::InvalidFloatLiteral { span, .. }
+            | ParseError::MissingDigits { span, .. }
+            | ParseError::InvalidDigit { span, .. } => *span,
+        }
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ParseError::UnexpectedToken { expected, found, .. } => {
+                write!(fmt, "expected next token to be '{}', got '{}' instead", expected, found)
+            },
+            ParseError::NoPrefixParseFn { found, .. } => {
+                write!(fmt, "no prefix parse function for '{}' found", found)
+            },
+            ParseError::InvalidIntegerLiteral { literal, .. } => {
+                write!(fmt, "could not parse '{}' as integer", literal)
+            },
+            ParseError::UnterminatedBlock { .. } => {
+                write!(fmt, "expected '{}' to close the block, got '{}' instead", TokenType::Rbrace, TokenType::EOF)
+            },
+            ParseError::UnterminatedString { .. } => {
+                write!(fmt, "unterminated string literal")
+            },
+            ParseError::InvalidEscape { escape, .. } => {
+                write!(fmt, "invalid escape sequence '{}' in string literal", escape)
+            },
+            ParseError::UnterminatedComment { .. } => {
+                write!(fmt, "unterminated block comment")
+            },
+            ParseError::InvalidFloatLiteral { literal, .. } => {
+                write!(fmt, "could not parse '{}' as float", literal)
+            },
+            ParseError::MissingDigits { literal, .. } => {
+                write!(fmt, "no digits after the prefix in integer literal '{}'", literal)
+            },
+            ParseError::InvalidDigit { literal, digit, radix, .. } => {
+                write!(fmt, "invalid digit '{}' in {} literal '{}'", digit, radix_name(*radix), literal)
+            },
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+pub struct Parser {
     l: Lexer,
     
-    errors: Vec<String>,
+    errors: Vec<ParseError>,
+    big_integers: bool,
 
     cur_token: Token,
     peek_token: Token,
+    // '{' minus '}' among the tokens before cur_token
+    brace_depth: usize,
 }
 
 impl Parser {
@@ -16,8 +170,10 @@ impl Parser {
         let mut p = Parser {
             l: lexer,
             errors: vec![],
+            big_integers: false,
             cur_token: Token::default(),
             peek_token: Token::default(),
+            brace_depth: 0,
         };
 
         p.next_token();
@@ -26,44 +182,117 @@ impl Parser {
         p
     }
 
-    fn errors(&self) -> &Vec<String> {
+    // integer literals too large for an i64 become big integers instead of an error,
+    // for programs evaluated with OverflowPolicy::Promote
+    pub fn with_big_integers(mut self, big_integers: bool) -> Parser {
+        self.big_integers = big_integers;
+        self
+    }
+
+    pub fn errors(&self) -> &Vec<ParseError> {
         &self.errors
     }
 
     fn peek_error(&mut self, typ: &TokenType) {
-        self.errors.push(format!("expected next token to be '{:?}', got '{:?}' instead", typ, self.peek_token.typ));
+        self.errors.push(ParseError::UnexpectedToken {
+            expected: typ.clone(),
+            found: self.peek_token.typ.clone(),
+            span: self.peek_token.span
+        });
     }
 
     fn next_token(&mut self) {
         let mut token = self.l.next_token();
+
+        // the comment swallowed the rest of the input, so the parser just sees the end of it
+        if token.typ == TokenType::UnterminatedComment {
+            self.errors.push(ParseError::UnterminatedComment { span: token.span });
+            let span = Span::new(token.span.end, token.span.end, token.span.line, token.span.col);
+            token = Token::new(TokenType::EOF, "");
+            token.span = span;
+        }
+
+        match self.cur_token.typ {
+            TokenType::Lbrace => self.brace_depth += 1,
+            TokenType::Rbrace => self.brace_depth = self.brace_depth.saturating_sub(1),
+            _ => {}
+        }
+
         std::mem::swap(&mut token, &mut self.peek_token);
         self.cur_token = token;
     }
 
-    fn parse_program(&mut self) -> Result<ast::Program, String> {
+    pub fn parse_program(&mut self) -> Result<ast::Program, Vec<ParseError>> {
         let mut program = ast::Program::new();
         while self.cur_token.typ != TokenType::EOF {
-            while let Some(stmt) = self.parse_statement() {
+            if let Some(stmt) = self.parse_next_statement() {
                 program.statements.push(stmt);
             }
-            self.next_token();
+        }
+
+        if !self.errors.is_empty() {
+            return Err(self.errors.clone());
         }
 
         Ok(program)
-        //Err("Invalid parse_program".to_string())
+    }
+
+    // parses one statement and moves to the token after it, or resynchronizes if it was malformed
+    fn parse_next_statement(&mut self) -> Option<ast::Statement> {
+        let start = self.cur_token.span.start;
+        let depth = self.brace_depth;
+
+        match self.parse_statement() {
+            Some(stmt) => {
+                self.next_token();
+                Some(stmt)
+            },
+            None => {
+                self.synchronize(start, depth);
+                None
+            }
+        }
+    }
+
+    // panic-mode recovery: skip past the next ';', or stop before a '}', a statement keyword or EOF.
+    // the broken statement's first token is always skipped so a bad 'let' can't be retried forever.
+    // only tokens back at the statement's brace depth count, so braces opened inside it are skipped
+    // up to their matching '}' and the stop is at the '}' closing the enclosing block
+    fn synchronize(&mut self, start: usize, depth: usize) {
+        if self.cur_token.span.start == start && !self.cur_token_is(TokenType::EOF) {
+            if self.cur_token_is(TokenType::Semicolon) {
+                self.next_token();
+                return;
+            }
+            self.next_token();
+        }
+
+        loop {
+            match self.cur_token.typ {
+                TokenType::EOF => return,
+                _ if self.brace_depth > depth => self.next_token(),
+                TokenType::Semicolon => {
+                    self.next_token();
+                    return;
+                },
+                TokenType::Rbrace | TokenType::Let | TokenType::Return => return,
+                _ => self.next_token()
+            }
+        }
     }
 
     fn parse_statement(&mut self) -> Option<ast::Statement> {
         match self.cur_token.typ {
             TokenType::Let => self.parse_let_statement(),
             TokenType::Return => self.parse_return_statement(),
-            _ => None
+            _ => self.parse_expression_statement()
         }
     }
 
     fn parse_let_statement(&mut self) -> Option<ast::Statement> {
-        //let token = self.cur_token.clone();
-        
+        let start = self.cur_token.span;
+        let doc = self.cur_token.doc.clone();
+
         if !self.expect_peek(TokenType::Ident) {
             return None;
         }
@@ -76,33 +305,430 @@ impl Parser {
             return None;
         }
 
-        while !self.cur_token_is(TokenType::Semicolon) {
+        self.next_token();
+        let value = self.parse_expression(Precedence::Lowest)?;
+
+        if self.peek_token_is(&TokenType::Semicolon) {
             self.next_token();
         }
 
-        Some(
-            ast::Statement::Let(
-                ident, 
-                ast::Expression::Identifier(
-                    ast::Identifier::new(Token::new(TokenType::Ident, &val), &val)
-                )
-            )
-        )
+        Some(ast::Statement::Let(ident, value, doc, start.to(self.cur_token.span)))
     }
 
     fn parse_return_statement(&mut self) -> Option<ast::Statement> {
+        let start = self.cur_token.span;
+        self.next_token();
+        let value = self.parse_expression(Precedence::Lowest)?;
+
+        if self.peek_token_is(&TokenType::Semicolon) {
+            self.next_token();
+        }
+
+        Some(ast::Statement::Return(value, start.to(self.cur_token.span)))
+    }
+
+    fn parse_expression_statement(&mut self) -> Option<ast::Statement> {
+        let exp = self.parse_expression(Precedence::Lowest)?;
+
+        if self.peek_token_is(&TokenType::Semicolon) {
+            self.next_token();
+        }
+
+        let span = exp.span().to(self.cur_token.span);
+        Some(ast::Statement::Expression(exp, span))
+    }
+
+    fn parse_expression(&mut self, precedence: Precedence) -> Option<ast::Expression> {
+        let mut left = match self.cur_token.typ {
+            TokenType::Ident => self.parse_identifier(),
+            TokenType::Int => self.parse_integer_literal(),
+            TokenType::Float => self.parse_float_literal(),
+            TokenType::String => self.parse_string_literal(),
+            TokenType::UnterminatedString => {
+                self.errors.push(ParseError::UnterminatedString { span: self.cur_token.span });
+                None
+            },
+            TokenType::InvalidEscape => {
+                self.errors.push(ParseError::InvalidEscape {
+                    escape: self.cur_token.literal.clone(),
+                    span: self.cur_token.span
+                });
+                None
+            },
+            TokenType::True | TokenType::False => self.parse_boolean(),
+            TokenType::Bang | TokenType::Minus => self.parse_prefix_expression(),
+            TokenType::Lparen => self.parse_grouped_expression(),
+            TokenType::If => self.parse_if_expression(),
+            TokenType::Function => self.parse_function_literal(),
+            TokenType::Lbracket => self.parse_array_literal(),
+            TokenType::Lbrace => self.parse_hash_literal(),
+            _ => {
+                self.no_prefix_parse_fn_error();
+                None
+            }
+        }?;
+
+        while !self.peek_token_is(&TokenType::Semicolon) && precedence < self.peek_precedence() {
+            left = match self.peek_token.typ {
+                TokenType::Plus
+                | TokenType::Minus
+                | TokenType::Slash
+                | TokenType::Asterisk
+                | TokenType::Equal
+                | TokenType::NotEqual
+                | TokenType::LT
+                | TokenType::GT => {
+                    self.next_token();
+                    self.parse_infix_expression(left)?
+                },
+                TokenType::Lparen => {
+                    self.next_token();
+                    self.parse_call_expression(left)?
+                },
+                TokenType::Lbracket => {
+                    self.next_token();
+                    self.parse_index_expression(left)?
+                },
+                _ => return Some(left)
+            };
+        }
+
+        Some(left)
+    }
+
+    fn parse_identifier(&mut self) -> Option<ast::Expression> {
+        let tok = self.cur_token.clone();
+        let val = tok.literal.clone();
+        Some(ast::Expression::Identifier(ast::Identifier::new(tok, &val)))
+    }
+
+    fn parse_integer_literal(&mut self) -> Option<ast::Expression> {
+        let literal = self.cur_token.literal.clone();
+        let span = self.cur_token.span;
+
+        let (radix, prefix_len) = match literal.get(..2) {
+            Some("0x") | Some("0X") => (16, 2),
+            Some("0o") | Some("0O") => (8, 2),
+            Some("0b") | Some("0B") => (2, 2),
+            _ => (10, 0)
+        };
+        let digits = &literal[prefix_len..];
+
+        if let Some((offset, digit)) = digits.char_indices().find(|(_, ch)| *ch != '_' && !ch.is_digit(radix)) {
+            let start = span.start + prefix_len + offset;
+            let col = span.col + literal[..prefix_len + offset].chars().count();
+            self.errors.push(ParseError::InvalidDigit {
+                literal,
+                digit,
+                radix,
+                span: Span::new(start, start + digit.len_utf8(), span.line, col)
+            });
+            return None;
+        }
+
+        let digits = digits.replace('_', "");
+        if digits.is_empty() {
+            self.errors.push(ParseError::MissingDigits { literal, span });
+            return None;
+        }
+
+        match i64::from_str_radix(&digits, radix) {
+            Ok(value) => Some(ast::Expression::Literal(ast::Literal::Int(value), span)),
+            Err(_) if self.big_integers => {
+                // the digits were all checked above, so only the size could have failed
+                let value = BigInt::parse_bytes(digits.as_bytes(), radix)?;
+                Some(ast::Expression::Literal(ast::Literal::BigInt(value), span))
+            },
+            Err(_) => {
+                self.errors.push(ParseError::InvalidIntegerLiteral { literal, span });
+                None
+            }
+        }
+    }
+
+    // the lexer only produces well formed floats, but they can still be too big for an f64
+    fn parse_float_literal(&mut self) -> Option<ast::Expression> {
+        match self.cur_token.literal.replace('_', "").parse::<f64>() {
+            Ok(value) if value.is_finite() => Some(ast::Expression::Literal(ast::Literal::Float(value), self.cur_token.span)),
+            _ => {
+                self.errors.push(ParseError::InvalidFloatLiteral {
+                    literal: self.cur_token.literal.clone(),
+                    span: self.cur_token.span
+                });
+                None
+            }
+        }
+    }
+
+    fn parse_string_literal(&mut self) -> Option<ast::Expression> {
+        let value = ast::Literal::String(self.cur_token.literal.clone());
+        Some(ast::Expression::Literal(value, self.cur_token.span))
+    }
+
+    fn parse_boolean(&mut self) -> Option<ast::Expression> {
+        let value = self.cur_token_is(TokenType::True);
+        Some(ast::Expression::Literal(ast::Literal::Bool(value), self.cur_token.span))
+    }
+
+    fn parse_prefix_expression(&mut self) -> Option<ast::Expression> {
+        let start = self.cur_token.span;
+        let operator = self.cur_token.literal.clone();
+        self.next_token();
+        let right = self.parse_expression(Precedence::Prefix)?;
+
+        Some(ast::Expression::Prefix {
+            operator,
+            right: Box::new(right),
+            span: start.to(self.cur_token.span)
+        })
+    }
+
+    fn parse_infix_expression(&mut self, left: ast::Expression) -> Option<ast::Expression> {
+        let operator = self.cur_token.literal.clone();
+        let precedence = self.cur_precedence();
+        self.next_token();
+        let right = self.parse_expression(precedence)?;
+        let span = left.span().to(self.cur_token.span);
+
+        Some(ast::Expression::Infix {
+            left: Box::new(left),
+            operator,
+            right: Box::new(right),
+            span
+        })
+    }
+
+    fn parse_grouped_expression(&mut self) -> Option<ast::Expression> {
+        let start = self.cur_token.span;
+        self.next_token();
+        let mut exp = self.parse_expression(Precedence::Lowest)?;
+
+        if !self.expect_peek(TokenType::Rparen) {
+            return None;
+        }
+
+        // there is no node for the parens, so the inner expression covers them
+        *exp.span_mut() = start.to(self.cur_token.span);
+        Some(exp)
+    }
+
+    fn parse_if_expression(&mut self) -> Option<ast::Expression> {
+        let start = self.cur_token.span;
+
+        if !self.expect_peek(TokenType::Lparen) {
+            return None;
+        }
+
+        self.next_token();
+        let condition = self.parse_expression(Precedence::Lowest)?;
+
+        if !self.expect_peek(TokenType::Rparen) {
+            return None;
+        }
+
+        if !self.expect_peek(TokenType::Lbrace) {
+            return None;
+        }
+
+        let consequence = self.parse_block_statement()?;
+
+        let alternative = if self.peek_token_is(&TokenType::Else) {
+            self.next_token();
+
+            if !self.expect_peek(TokenType::Lbrace) {
+                return None;
+            }
+
+            Some(self.parse_block_statement()?)
+        } else {
+            None
+        };
+
+        Some(ast::Expression::If {
+            condition: Box::new(condition),
+            consequence,
+            alternative,
+            span: start.to(self.cur_token.span)
+        })
+    }
+
+    fn parse_block_statement(&mut self) -> Option<ast::BlockStatement> {
+        let mut block = ast::BlockStatement::new();
+        let lbrace_span = self.cur_token.span;
+        self.next_token();
+
+        while !self.cur_token_is(TokenType::Rbrace) && !self.cur_token_is(TokenType::EOF) {
+            if let Some(stmt) = self.parse_next_statement() {
+                block.statements.push(stmt);
+            }
+        }
+
+        if self.cur_token_is(TokenType::EOF) {
+            self.errors.push(ParseError::UnterminatedBlock { span: lbrace_span });
+            return None;
+        }
+
+        block.span = lbrace_span.to(self.cur_token.span);
+        Some(block)
+    }
+
+    fn parse_function_literal(&mut self) -> Option<ast::Expression> {
+        let start = self.cur_token.span;
+
+        if !self.expect_peek(TokenType::Lparen) {
+            return None;
+        }
+
+        let params = self.parse_function_parameters()?;
+
+        if !self.expect_peek(TokenType::Lbrace) {
+            return None;
+        }
+
+        let body = self.parse_block_statement()?;
+
+        Some(ast::Expression::FunctionLiteral {
+            params,
+            body,
+            span: start.to(self.cur_token.span)
+        })
+    }
+
+    fn parse_function_parameters(&mut self) -> Option<Vec<ast::Identifier>> {
+        let mut params = vec![];
+
+        if self.peek_token_is(&TokenType::Rparen) {
+            self.next_token();
+            return Some(params);
+        }
+
+        if !self.expect_peek(TokenType::Ident) {
+            return None;
+        }
+        params.push(ast::Identifier::new(self.cur_token.clone(), &self.cur_token.literal));
+
+        while self.peek_token_is(&TokenType::Comma) {
+            self.next_token();
+
+            if !self.expect_peek(TokenType::Ident) {
+                return None;
+            }
+            params.push(ast::Identifier::new(self.cur_token.clone(), &self.cur_token.literal));
+        }
+
+        if !self.expect_peek(TokenType::Rparen) {
+            return None;
+        }
+
+        Some(params)
+    }
+
+    fn parse_call_expression(&mut self, function: ast::Expression) -> Option<ast::Expression> {
+        let arguments = self.parse_expression_list(TokenType::Rparen)?;
+        let span = function.span().to(self.cur_token.span);
+
+        Some(ast::Expression::Call {
+            function: Box::new(function),
+            arguments,
+            span
+        })
+    }
+
+    fn parse_array_literal(&mut self) -> Option<ast::Expression> {
+        let start = self.cur_token.span;
+        let elements = self.parse_expression_list(TokenType::Rbracket)?;
+
+        Some(ast::Expression::ArrayLiteral {
+            elements,
+            span: start.to(self.cur_token.span)
+        })
+    }
+
+    fn parse_hash_literal(&mut self) -> Option<ast::Expression> {
+        let start = self.cur_token.span;
+        let mut pairs = vec![];
+
+        while !self.peek_token_is(&TokenType::Rbrace) {
+            self.next_token();
+            let key = self.parse_expression(Precedence::Lowest)?;
+
+            if !self.expect_peek(TokenType::Colon) {
+                return None;
+            }
+
+            self.next_token();
+            let value = self.parse_expression(Precedence::Lowest)?;
+            pairs.push((key, value));
+
+            if !self.peek_token_is(&TokenType::Rbrace) && !self.expect_peek(TokenType::Comma) {
+                return None;
+            }
+        }
+
+        if !self.expect_peek(TokenType::Rbrace) {
+            return None;
+        }
+
+        Some(ast::Expression::Hash {
+            pairs,
+            span: start.to(self.cur_token.span)
+        })
+    }
+
+    fn parse_index_expression(&mut self, left: ast::Expression) -> Option<ast::Expression> {
+        self.next_token();
+        let index = self.parse_expression(Precedence::Lowest)?;
+
+        if !self.expect_peek(TokenType::Rbracket) {
+            return None;
+        }
+
+        let span = left.span().to(self.cur_token.span);
+        Some(ast::Expression::Index {
+            left: Box::new(left),
+            index: Box::new(index),
+            span
+        })
+    }
+
+    // comma separated expressions up to the closing token, for call arguments and array elements
+    fn parse_expression_list(&mut self, end: TokenType) -> Option<Vec<ast::Expression>> {
+        let mut args = vec![];
+
+        if self.peek_token_is(&end) {
+            self.next_token();
+            return Some(args);
+        }
+
         self.next_token();
-        while !self.cur_token_is(TokenType::Semicolon) {
+        args.push(self.parse_expression(Precedence::Lowest)?);
+
+        while self.peek_token_is(&TokenType::Comma) {
+            self.next_token();
             self.next_token();
+            args.push(self.parse_expression(Precedence::Lowest)?);
+        }
+
+        if !self.expect_peek(end) {
+            return None;
         }
 
-        Some(
-            ast::Statement::Return(
-                ast::Expression::Identifier(
-                    ast::Identifier::new(Token::new(TokenType::String, ""), "") //TODO don't use strings as empty values
-                )
-            )
-        )
+        Some(args)
+    }
+
+    fn no_prefix_parse_fn_error(&mut self) {
+        self.errors.push(ParseError::NoPrefixParseFn {
+            found: self.cur_token.typ.clone(),
+            span: self.cur_token.span
+        });
+    }
+
+    fn peek_precedence(&self) -> Precedence {
+        token_precedence(&self.peek_token.typ)
+    }
+
+    fn cur_precedence(&self) -> Precedence {
+        token_precedence(&self.cur_token.typ)
     }
 
     fn cur_token_is(&self, typ: TokenType) -> bool {
@@ -124,6 +750,11 @@ impl Parser {
     }
 }
 
+// lexes and parses a whole source, collecting every parser error
+pub fn parse(input: &str) -> Result<ast::Program, Vec<ParseError>> {
+    Parser::new(Lexer::new(input)).parse_program()
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -141,28 +772,29 @@ mod test {
 
         let r_program = p.parse_program();
         if let Err(err) = check_parser_errors(&p) {
-            panic!(err);
+            panic!("{}", err);
         }
 
         match r_program {
             Ok(program) => {
                 assert!(program.statements.len() == 3, "program.statements does not contain 3 statements. got={}", program.statements.len());
                 
-                let tests = vec![
-                    "x",
-                    "y",
-                    "foobar"
+                let tests = [
+                    ("x", "5"),
+                    ("y", "10"),
+                    ("foobar", "838383")
                 ];
 
-                for (i, e_ident) in tests.iter().enumerate() {
+                for (i, (e_ident, e_value)) in tests.iter().enumerate() {
                     match program.statements.get(i) {
                         None => panic!("Invalid statement index"),
                         Some(stmt) => {
                             match stmt {
-                                ast::Statement::Let(ident, _expression) => {
+                                ast::Statement::Let(ident, expression, ..) => {
                                     assert_eq!(stmt.token_literal(), "let", "token_litral is not 'let'. got={}", stmt.token_literal());
                                     assert_eq!(*e_ident, ident.value, "Expected identifier={}. got={}", e_ident, ident.value);
                                     assert_eq!(*e_ident, ident.token_literal(), "Expected identifier={}. got={}", e_ident, ident.token_literal());
+                                    assert_eq!(*e_value, expression.to_string(), "Expected value={}. got={}", e_value, expression);
                                 },
                                 _ => panic!("Invalid ast.Statement, expected 'let'")
                             }
@@ -171,14 +803,14 @@ mod test {
                 }
                 
             },
-            Err(e) => {
-                panic!("{}", e);
+            Err(errors) => {
+                panic!("parse_program returned errors: {:?}", errors);
             }
         }
     }
 
     fn check_parser_errors(p: &Parser) -> Result<(), String> {
-        if p.errors.len() == 0 {
+        if p.errors.is_empty() {
             return Ok(());
         }
 
@@ -204,18 +836,659 @@ mod test {
 
         let r_program = p.parse_program();
         if let Err(err) = check_parser_errors(&p) {
-            panic!(err);
+            panic!("{}", err);
         }
 
         match r_program {
-            Err(e) => panic!("{}", e),
+            Err(errors) => panic!("parse_program returned errors: {:?}", errors),
             Ok(program) => {
                 assert!(program.statements.len() == 3, "program.statements does not contain 3 statements. got={}", program.statements.len());
 
-                for stmt in &program.statements {
+                let tests = ["5", "10", "993322"];
+                for (stmt, e_value) in program.statements.iter().zip(tests.iter()) {
                     assert_eq!(stmt.token_literal(), "return", "token_litral is not 'return'. got={}", stmt.token_literal());
+                    match stmt {
+                        ast::Statement::Return(exp, _) => {
+                            assert_eq!(*e_value, exp.to_string(), "Expected return value={}. got={}", e_value, exp);
+                        },
+                        _ => panic!("Invalid ast.Statement, expected 'return'")
+                    }
                 }
             }
         }
     }
+
+    fn parse(input: &str) -> ast::Program {
+        let l = Lexer::new(input);
+        let mut p = Parser::new(l);
+
+        let r_program = p.parse_program();
+        if let Err(err) = check_parser_errors(&p) {
+            panic!("{}", err);
+        }
+
+        match r_program {
+            Ok(program) => program,
+            Err(errors) => panic!("parse_program returned errors: {:?}", errors)
+        }
+    }
+
+    fn single_expression(program: &ast::Program) -> &ast::Expression {
+        assert_eq!(program.statements.len(), 1, "program.statements does not contain 1 statement. got={}", program.statements.len());
+        match &program.statements[0] {
+            ast::Statement::Expression(exp, _) => exp,
+            stmt => panic!("program.statements[0] is not an expression statement. got={}", stmt)
+        }
+    }
+
+    fn test_literal_expression(exp: &ast::Expression, expected: &str) {
+        match exp {
+            ast::Expression::Identifier(ident) => {
+                assert_eq!(ident.value, expected, "ident.value not {}. got={}", expected, ident.value);
+                assert_eq!(ident.token_literal(), expected, "ident.token_literal() not {}. got={}", expected, ident.token_literal());
+            },
+            ast::Expression::Literal(lit, _) => {
+                assert_eq!(lit.to_string(), expected, "literal not {}. got={}", expected, lit);
+            },
+            _ => panic!("exp is not a literal expression. got={}", exp)
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let tests = [
+            ("let = 5;", ParseError::UnexpectedToken { expected: TokenType::Ident, found: TokenType::Assign, span: Span::new(4, 5, 1, 5) }),
+            ("let x 5;", ParseError::UnexpectedToken { expected: TokenType::Assign, found: TokenType::Int, span: Span::new(6, 7, 1, 7) }),
+            ("1 +\n  * 2", ParseError::NoPrefixParseFn { found: TokenType::Asterisk, span: Span::new(6, 7, 2, 3) }),
+            ("99999999999999999999", ParseError::InvalidIntegerLiteral { literal: "99999999999999999999".to_string(), span: Span::new(0, 20, 1, 1) }),
+            ("if (x) {\n  x", ParseError::UnterminatedBlock { span: Span::new(7, 8, 1, 8) }),
+            ("let s = \"abc", ParseError::UnterminatedString { span: Span::new(8, 12, 1, 9) }),
+            ("\"a\\qb\"", ParseError::InvalidEscape { escape: "\\q".to_string(), span: Span::new(0, 6, 1, 1) }),
+            ("let x = 1; /* /* */", ParseError::UnterminatedComment { span: Span::new(11, 19, 1, 12) }),
+            ("1e400", ParseError::InvalidFloatLiteral { literal: "1e400".to_string(), span: Span::new(0, 5, 1, 1) }),
+            ("let x = 0x;", ParseError::MissingDigits { literal: "0x".to_string(), span: Span::new(8, 10, 1, 9) }),
+            ("0b_", ParseError::MissingDigits { literal: "0b_".to_string(), span: Span::new(0, 3, 1, 1) }),
+            ("x + 0b102", ParseError::InvalidDigit { literal: "0b102".to_string(), digit: '2', radix: 2, span: Span::new(8, 9, 1, 9) }),
+            ("0o78", ParseError::InvalidDigit { literal: "0o78".to_string(), digit: '8', radix: 8, span: Span::new(3, 4, 1, 4) }),
+            ("0xFG", ParseError::InvalidDigit { literal: "0xFG".to_string(), digit: 'G', radix: 16, span: Span::new(3, 4, 1, 4) }),
+            ("0x8000000000000000", ParseError::InvalidIntegerLiteral { literal: "0x8000000000000000".to_string(), span: Span::new(0, 18, 1, 1) }),
+        ];
+
+        for (input, expected) in tests.iter() {
+            match Parser::new(Lexer::new(input)).parse_program() {
+                Err(errors) => assert_eq!(errors[0], *expected, "wrong first error. expected={:?}, got={:?}", expected, errors[0]),
+                Ok(program) => panic!("no parser errors for '{}'. got={}", input, program)
+            }
+        }
+    }
+
+    #[test]
+    fn test_big_integer_literals() {
+        let tests = [
+            ("9223372036854775808", "9223372036854775808"),
+            ("0x1_0000_0000_0000_0000", "18446744073709551616"),
+            ("-99999999999999999999", "(-99999999999999999999)"),
+        ];
+
+        for (input, expected) in tests.iter() {
+            let program = match Parser::new(Lexer::new(input)).with_big_integers(true).parse_program() {
+                Ok(program) => program,
+                Err(errors) => panic!("parser has {} errors for '{}': {:?}", errors.len(), input, errors)
+            };
+            let exp = single_expression(&program);
+            assert_eq!(exp.to_string(), *expected, "wrong literal. expected={}, got={}", expected, exp);
+        }
+
+        // literals that fit stay plain integers
+        let program = Parser::new(Lexer::new("9223372036854775807")).with_big_integers(true).parse_program();
+        match program.as_ref().map(single_expression) {
+            Ok(ast::Expression::Literal(ast::Literal::Int(i), _)) => assert_eq!(*i, i64::MAX, "wrong value. got={}", i),
+            exp => panic!("exp is not an integer literal. got={:?}", exp)
+        }
+    }
+
+    #[test]
+    fn test_parse_error_messages() {
+        let tests = [
+            ("let = 5;", "expected next token to be 'Ident', got '=' instead"),
+            ("1 +\n  * 2", "no prefix parse function for '*' found"),
+            ("99999999999999999999", "could not parse '99999999999999999999' as integer"),
+            ("if (x) {\n  x", "expected '}' to close the block, got 'EOF' instead"),
+            ("0x", "no digits after the prefix in integer literal '0x'"),
+            ("0b102", "invalid digit '2' in binary literal '0b102'"),
+        ];
+
+        for (input, expected) in tests.iter() {
+            match parse_errors(input).first() {
+                Some(err) => assert_eq!(err.to_string(), *expected, "wrong error message. expected={}, got={}", expected, err),
+                None => panic!("no parser errors for '{}'", input)
+            }
+        }
+    }
+
+    fn parse_errors(input: &str) -> Vec<ParseError> {
+        match super::parse(input) {
+            Err(errors) => errors,
+            Ok(_) => vec![]
+        }
+    }
+
+    #[test]
+    fn test_identifier_expression() {
+        let program = parse("foobar;");
+        test_literal_expression(single_expression(&program), "foobar");
+    }
+
+    #[test]
+    fn test_integer_literal_expression() {
+        let program = parse("5;");
+        match single_expression(&program) {
+            ast::Expression::Literal(ast::Literal::Int(value), _) => {
+                assert_eq!(*value, 5, "literal value not 5. got={}", value);
+            },
+            exp => panic!("exp is not an integer literal. got={}", exp)
+        }
+    }
+
+    #[test]
+    fn test_prefixed_integer_literals() {
+        let tests = [
+            ("0xFF;", 255),
+            ("0Xff;", 255),
+            ("0o755;", 493),
+            ("0b1010;", 10),
+            ("1_000_000;", 1000000),
+            ("0x_7FFF_FFFF_FFFF_FFFF;", i64::MAX),
+            ("0b1__1_;", 3),
+        ];
+
+        for (input, expected) in tests.iter() {
+            let program = parse(input);
+            match single_expression(&program) {
+                ast::Expression::Literal(ast::Literal::Int(value), _) => {
+                    assert_eq!(*value, *expected, "literal value wrong. expected={}, got={}", expected, value);
+                },
+                exp => panic!("exp is not an integer literal. got={}", exp)
+            }
+        }
+    }
+
+    #[test]
+    fn test_float_literal_expression() {
+        let tests = [
+            ("2.75;", 2.75),
+            ("1e-9;", 1e-9),
+            (".5;", 0.5),
+            ("2.5E+3;", 2500.0),
+            ("10e2;", 1000.0),
+            ("1_000.25;", 1000.25),
+        ];
+
+        for (input, expected) in tests.iter() {
+            let program = parse(input);
+            match single_expression(&program) {
+                ast::Expression::Literal(ast::Literal::Float(value), _) => {
+                    assert_eq!(*value, *expected, "literal value wrong. expected={}, got={}", expected, value);
+                },
+                exp => panic!("exp is not a float literal. got={}", exp)
+            }
+        }
+    }
+
+    #[test]
+    fn test_string_literal_expression() {
+        let program = parse("\"hello\\n \\\"world\\\"\";");
+        match single_expression(&program) {
+            ast::Expression::Literal(ast::Literal::String(value), _) => {
+                assert_eq!(value, "hello\n \"world\"", "literal value wrong. got={:?}", value);
+            },
+            exp => panic!("exp is not a string literal. got={}", exp)
+        }
+
+        assert_eq!(program.to_string(), "\"hello\\n \\\"world\\\"\";", "string literal display is wrong. got={}", program);
+    }
+
+    #[test]
+    fn test_boolean_expression() {
+        let tests = [
+            ("true;", true),
+            ("false;", false),
+        ];
+
+        for (input, expected) in tests.iter() {
+            let program = parse(input);
+            match single_expression(&program) {
+                ast::Expression::Literal(ast::Literal::Bool(value), _) => {
+                    assert_eq!(value, expected, "literal value not {}. got={}", expected, value);
+                },
+                exp => panic!("exp is not a boolean literal. got={}", exp)
+            }
+        }
+    }
+
+    #[test]
+    fn test_parsing_prefix_expressions() {
+        let tests = [
+            ("!5;", "!", "5"),
+            ("-15;", "-", "15"),
+            ("!foobar;", "!", "foobar"),
+            ("-foobar;", "-", "foobar"),
+            ("!true;", "!", "true"),
+            ("!false;", "!", "false"),
+        ];
+
+        for (input, e_operator, e_right) in tests.iter() {
+            let program = parse(input);
+            match single_expression(&program) {
+                ast::Expression::Prefix { operator, right, .. } => {
+                    assert_eq!(operator, e_operator, "exp.operator is not '{}'. got={}", e_operator, operator);
+                    test_literal_expression(right, e_right);
+                },
+                exp => panic!("exp is not a prefix expression. got={}", exp)
+            }
+        }
+    }
+
+    #[test]
+    fn test_parsing_infix_expressions() {
+        let tests = [
+            ("5 + 5;", "5", "+", "5"),
+            ("5 - 5;", "5", "-", "5"),
+            ("5 * 5;", "5", "*", "5"),
+            ("5 / 5;", "5", "/", "5"),
+            ("5 > 5;", "5", ">", "5"),
+            ("5 < 5;", "5", "<", "5"),
+            ("5 == 5;", "5", "==", "5"),
+            ("5 != 5;", "5", "!=", "5"),
+            ("foobar + barfoo;", "foobar", "+", "barfoo"),
+            ("true == true", "true", "==", "true"),
+            ("true != false", "true", "!=", "false"),
+        ];
+
+        for (input, e_left, e_operator, e_right) in tests.iter() {
+            let program = parse(input);
+            match single_expression(&program) {
+                ast::Expression::Infix { left, operator, right, .. } => {
+                    test_literal_expression(left, e_left);
+                    assert_eq!(operator, e_operator, "exp.operator is not '{}'. got={}", e_operator, operator);
+                    test_literal_expression(right, e_right);
+                },
+                exp => panic!("exp is not an infix expression. got={}", exp)
+            }
+        }
+    }
+
+    #[test]
+    fn test_operator_precedence_parsing() {
+        let tests = [
+            ("-a * b", "((-a) * b);"),
+            ("!-a", "(!(-a));"),
+            ("a + b + c", "((a + b) + c);"),
+            ("a + b - c", "((a + b) - c);"),
+            ("a * b * c", "((a * b) * c);"),
+            ("a * b / c", "((a * b) / c);"),
+            ("a + b / c", "(a + (b / c));"),
+            ("a + b * c + d / e - f", "(((a + (b * c)) + (d / e)) - f);"),
+            ("3 + 4; -5 * 5", "(3 + 4);\n((-5) * 5);"),
+            ("5 > 4 == 3 < 4", "((5 > 4) == (3 < 4));"),
+            ("5 < 4 != 3 > 4", "((5 < 4) != (3 > 4));"),
+            ("3 + 4 * 5 == 3 * 1 + 4 * 5", "((3 + (4 * 5)) == ((3 * 1) + (4 * 5)));"),
+            ("true", "true;"),
+            ("3 > 5 == false", "((3 > 5) == false);"),
+            ("1 + (2 + 3) + 4", "((1 + (2 + 3)) + 4);"),
+            ("(5 + 5) * 2", "((5 + 5) * 2);"),
+            ("-(5 + 5)", "(-(5 + 5));"),
+            ("!(true == true)", "(!(true == true));"),
+            ("a + add(b * c) + d", "((a + add((b * c))) + d);"),
+            ("add(a, b, 1, 2 * 3, 4 + 5, add(6, 7 * 8))", "add(a, b, 1, (2 * 3), (4 + 5), add(6, (7 * 8)));"),
+            ("add(a + b + c * d / f + g)", "add((((a + b) + ((c * d) / f)) + g));"),
+            ("a * [1, 2, 3, 4][b * c] * d", "((a * ([1, 2, 3, 4][(b * c)])) * d);"),
+            ("add(a * b[2], b[1], 2 * [1, 2][1])", "add((a * (b[2])), (b[1]), (2 * ([1, 2][1])));"),
+            ("{\"a\": 1 + 2, b: {}}[\"a\"]", "({\"a\": (1 + 2), b: {}}[\"a\"]);"),
+            ("f(x)[0][1]", "((f(x)[0])[1]);"),
+        ];
+
+        for (input, expected) in tests.iter() {
+            let program = parse(input);
+            assert_eq!(program.to_string(), *expected, "expected={}, got={}", expected, program);
+        }
+    }
+
+    #[test]
+    fn test_if_expression() {
+        let program = parse("if (x < y) { x }");
+        match single_expression(&program) {
+            ast::Expression::If { condition, consequence, alternative, .. } => {
+                assert_eq!(condition.to_string(), "(x < y)", "condition is wrong. got={}", condition);
+                assert_eq!(consequence.statements.len(), 1, "consequence is not 1 statement. got={}", consequence.statements.len());
+                match &consequence.statements[0] {
+                    ast::Statement::Expression(exp, _) => test_literal_expression(exp, "x"),
+                    stmt => panic!("consequence.statements[0] is not an expression statement. got={}", stmt)
+                }
+                assert!(alternative.is_none(), "alternative was not None. got={:?}", alternative);
+            },
+            exp => panic!("exp is not an if expression. got={}", exp)
+        }
+    }
+
+    #[test]
+    fn test_if_else_expression() {
+        let program = parse("if (x < y) { x } else { y }");
+        match single_expression(&program) {
+            ast::Expression::If { condition, consequence, alternative, .. } => {
+                assert_eq!(condition.to_string(), "(x < y)", "condition is wrong. got={}", condition);
+                assert_eq!(consequence.to_string(), "{ x; }", "consequence is wrong. got={}", consequence);
+                match alternative {
+                    Some(alt) => assert_eq!(alt.to_string(), "{ y; }", "alternative is wrong. got={}", alt),
+                    None => panic!("alternative was None")
+                }
+            },
+            exp => panic!("exp is not an if expression. got={}", exp)
+        }
+    }
+
+    #[test]
+    fn test_function_literal_parsing() {
+        let program = parse("fn(x, y) { x + y; }");
+        match single_expression(&program) {
+            ast::Expression::FunctionLiteral { params, body, .. } => {
+                assert_eq!(params.len(), 2, "function literal params wrong. want 2, got={}", params.len());
+                assert_eq!(params[0].value, "x", "params[0] is not 'x'. got={}", params[0]);
+                assert_eq!(params[1].value, "y", "params[1] is not 'y'. got={}", params[1]);
+                assert_eq!(body.statements.len(), 1, "body.statements has not 1 statement. got={}", body.statements.len());
+                assert_eq!(body.to_string(), "{ (x + y); }", "body is wrong. got={}", body);
+            },
+            exp => panic!("exp is not a function literal. got={}", exp)
+        }
+    }
+
+    #[test]
+    fn test_function_parameter_parsing() {
+        let tests = [
+            ("fn() {};", vec![]),
+            ("fn(x) {};", vec!["x"]),
+            ("fn(x, y, z) {};", vec!["x", "y", "z"]),
+        ];
+
+        for (input, e_params) in tests.iter() {
+            let program = parse(input);
+            match single_expression(&program) {
+                ast::Expression::FunctionLiteral { params, .. } => {
+                    let params = params.iter().map(|p| p.value.as_str()).collect::<Vec<_>>();
+                    assert_eq!(params, *e_params, "params are wrong. expected={:?}, got={:?}", e_params, params);
+                },
+                exp => panic!("exp is not a function literal. got={}", exp)
+            }
+        }
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        let tests = [
+            "if (x < y) { x }",
+            "if (!x) { let a = 1; a } else { return -y; }",
+            "let add = fn(a, b) { a + b; };",
+            "fn() { if (true) { fn(x) { x } } else { } }",
+            "fn(x) { x }(5)",
+            "add(1, add(2, fn(a) { a }(3)))",
+            "let s = \"tab\\t quote\\\" slash\\\\ \\u{7}\";",
+            "let f = 1.0 * .5 + 1e-9 - 2e20;",
+            "[1, [a[0]], []][2 * i]",
+            // consecutive expression statements must not merge into a call or an index
+            "1\n!2.5",
+            "x; -y",
+            "a\n[0]; b; (c)",
+            "if (a) { b } else { c }\n(d)",
+            "{\"a\": [1, 2.5e10], true: \"s\"}; [3]",
+            "let x = 1; return x; !x",
+        ];
+
+        for input in tests.iter() {
+            let first = parse(input).to_string();
+            let second = parse(&first).to_string();
+            assert_eq!(first, second, "display output does not parse back to the same tree. first={}, second={}", first, second);
+        }
+
+        let printed = parse("x; -y").to_string();
+        assert_eq!(printed, "x;\n(-y);", "expression statements should be terminated. got={}", printed);
+    }
+
+    #[test]
+    fn test_array_literal_parsing() {
+        let program = parse("[1, 2 * 2, 3 + 3]");
+        match single_expression(&program) {
+            ast::Expression::ArrayLiteral { elements, span } => {
+                assert_eq!(elements.len(), 3, "wrong length of elements. got={}", elements.len());
+                test_literal_expression(&elements[0], "1");
+                assert_eq!(elements[1].to_string(), "(2 * 2)", "wrong element. got={}", elements[1]);
+                assert_eq!(elements[2].to_string(), "(3 + 3)", "wrong element. got={}", elements[2]);
+                assert_eq!(*span, Span::new(0, 17, 1, 1), "wrong span. got={:?}", span);
+            },
+            exp => panic!("exp is not an array literal. got={}", exp)
+        }
+
+        let program = parse("[]");
+        match single_expression(&program) {
+            ast::Expression::ArrayLiteral { elements, .. } => assert!(elements.is_empty(), "array should be empty. got={:?}", elements),
+            exp => panic!("exp is not an array literal. got={}", exp)
+        }
+    }
+
+    #[test]
+    fn test_index_expression_parsing() {
+        let program = parse("myArray[1 + 1]");
+        match single_expression(&program) {
+            ast::Expression::Index { left, index, span } => {
+                test_literal_expression(left, "myArray");
+                assert_eq!(index.to_string(), "(1 + 1)", "wrong index. got={}", index);
+                assert_eq!(*span, Span::new(0, 14, 1, 1), "wrong span. got={:?}", span);
+            },
+            exp => panic!("exp is not an index expression. got={}", exp)
+        }
+
+        let errors = parse_errors("a[1");
+        assert_eq!(errors.len(), 1, "wrong number of errors. got={:?}", errors);
+        assert_eq!(errors[0].to_string(), "expected next token to be ']', got 'EOF' instead", "wrong error. got={}", errors[0]);
+    }
+
+    #[test]
+    fn test_hash_literal_parsing() {
+        let program = parse("{\"one\": 1, true: 2, 3: 4 * 5}");
+        match single_expression(&program) {
+            ast::Expression::Hash { pairs, span } => {
+                let expected = [("\"one\"", "1"), ("true", "2"), ("3", "(4 * 5)")];
+                assert_eq!(pairs.len(), expected.len(), "wrong number of pairs. got={}", pairs.len());
+                for ((key, value), (e_key, e_value)) in pairs.iter().zip(expected.iter()) {
+                    assert_eq!(key.to_string(), *e_key, "wrong key. expected={}, got={}", e_key, key);
+                    assert_eq!(value.to_string(), *e_value, "wrong value. expected={}, got={}", e_value, value);
+                }
+                assert_eq!(*span, Span::new(0, 29, 1, 1), "wrong span. got={:?}", span);
+            },
+            exp => panic!("exp is not a hash literal. got={}", exp)
+        }
+
+        let program = parse("{}");
+        match single_expression(&program) {
+            ast::Expression::Hash { pairs, .. } => assert!(pairs.is_empty(), "hash should be empty. got={:?}", pairs),
+            exp => panic!("exp is not a hash literal. got={}", exp)
+        }
+
+        let errors = parse_errors("{1: 2");
+        assert_eq!(errors.len(), 1, "wrong number of errors. got={:?}", errors);
+        assert_eq!(errors[0].to_string(), "expected next token to be ',', got 'EOF' instead", "wrong error. got={}", errors[0]);
+    }
+
+    #[test]
+    fn test_call_expression_parsing() {
+        let program = parse("add(1, 2 * 3, 4 + 5);");
+        match single_expression(&program) {
+            ast::Expression::Call { function, arguments, .. } => {
+                test_literal_expression(function, "add");
+                assert_eq!(arguments.len(), 3, "wrong length of arguments. got={}", arguments.len());
+                test_literal_expression(&arguments[0], "1");
+                assert_eq!(arguments[1].to_string(), "(2 * 3)", "arguments[1] is wrong. got={}", arguments[1]);
+                assert_eq!(arguments[2].to_string(), "(4 + 5)", "arguments[2] is wrong. got={}", arguments[2]);
+            },
+            exp => panic!("exp is not a call expression. got={}", exp)
+        }
+    }
+
+    #[test]
+    fn test_call_expression_with_function_literal() {
+        let program = parse("fn(x){x}(5)");
+        match single_expression(&program) {
+            ast::Expression::Call { function, arguments, .. } => {
+                match function.as_ref() {
+                    ast::Expression::FunctionLiteral { params, .. } => {
+                        assert_eq!(params.len(), 1, "function literal params wrong. want 1, got={}", params.len());
+                    },
+                    exp => panic!("call.function is not a function literal. got={}", exp)
+                }
+                assert_eq!(arguments.len(), 1, "wrong length of arguments. got={}", arguments.len());
+                test_literal_expression(&arguments[0], "5");
+            },
+            exp => panic!("exp is not a call expression. got={}", exp)
+        }
+    }
+
+    #[test]
+    fn test_node_spans() {
+        let input = "let x = (1 + 2) * y;\nadd(fn(a) { a }, -x);";
+        let program = parse(input);
+        let text = |span: Span| &input[span.start..span.end];
+
+        assert_eq!(text(program.statements[0].span()), "let x = (1 + 2) * y;");
+        assert_eq!(text(program.statements[1].span()), "add(fn(a) { a }, -x);");
+
+        match &program.statements[0] {
+            ast::Statement::Let(ident, value, ..) => {
+                assert_eq!(text(ident.span()), "x");
+                assert_eq!(text(value.span()), "(1 + 2) * y");
+                match value {
+                    ast::Expression::Infix { left, .. } => assert_eq!(text(left.span()), "(1 + 2)"),
+                    exp => panic!("exp is not an infix expression. got={}", exp)
+                }
+            },
+            stmt => panic!("statement is not a let. got={}", stmt)
+        }
+
+        match &program.statements[1] {
+            ast::Statement::Expression(ast::Expression::Call { arguments, .. }, _) => {
+                assert_eq!(text(arguments[0].span()), "fn(a) { a }");
+                assert_eq!(text(arguments[1].span()), "-x");
+                assert_eq!(arguments[1].span().line, 2, "wrong line for argument. got={}", arguments[1].span().line);
+            },
+            stmt => panic!("statement is not a call. got={}", stmt)
+        }
+    }
+
+    #[test]
+    fn test_node_at() {
+        let input = "let x = (1 + 2) * y;\nadd(fn(a) { a }, -x);";
+        let program = parse(input);
+        let text = |span: Span| &input[span.start..span.end];
+
+        let tests = [
+            (0, "let x = (1 + 2) * y;"),
+            (4, "x"),
+            (9, "1"),
+            (11, "(1 + 2)"),
+            (15, "(1 + 2) * y"),
+            (18, "y"),
+            (21, "add"),
+            (28, "a"),
+            (33, "a"),
+            (30, "fn(a) { a }"),
+            (38, "-x"),
+            (39, "x"),
+        ];
+
+        for (offset, expected) in tests.iter() {
+            match program.node_at(*offset) {
+                Some(node) => assert_eq!(text(node.span()), *expected, "wrong node at offset {}", offset),
+                None => panic!("no node found at offset {}", offset)
+            }
+        }
+
+        assert!(program.node_at(input.len()).is_none(), "found a node past the end of the input");
+    }
+
+    #[test]
+    fn test_error_recovery() {
+        let tests = [
+            ("let = 5; let y = 10; let = 3;", vec![(1, 5), (1, 26)]),
+            ("let x = 1 +; let y = ;\nreturn 2;", vec![(1, 12), (1, 22)]),
+            ("let x = 5\nlet y = * 2;\nlet z = add(1, 2;\nz", vec![(2, 9), (3, 17)]),
+            ("let f = fn(x) { let = x; x + };\nlet g = fn() { 1 }", vec![(1, 21), (1, 30)]),
+            ("if (x) { 1 + } else { let }\nlet ok = true;", vec![(1, 14), (1, 27)]),
+            ("} let x = 1; )", vec![(1, 1), (1, 14)]),
+            ("let f = fn(x { x };", vec![(1, 14)]),
+            ("let f = fn(a b) { a };", vec![(1, 14)]),
+            ("if (x { 1 } else { 2 }", vec![(1, 7)]),
+        ];
+
+        for (input, expected) in tests.iter() {
+            let locations = parse_errors(input).iter()
+                .map(|err| (err.span().line, err.span().col))
+                .collect::<Vec<_>>();
+
+            assert_eq!(locations, *expected, "wrong errors for '{}'. got={:?}", input, parse_errors(input));
+        }
+    }
+
+    #[test]
+    fn test_recovery_keeps_valid_statements() {
+        let mut p = Parser::new(Lexer::new("let a = 1; let = 2; let b = fn() { let = 3; 4 }; return a;"));
+        let mut statements = vec![];
+
+        while !p.cur_token_is(TokenType::EOF) {
+            if let Some(stmt) = p.parse_next_statement() {
+                statements.push(stmt.to_string());
+            }
+        }
+
+        assert_eq!(p.errors().len(), 2, "wrong number of errors. got={:?}", p.errors());
+        assert_eq!(statements, vec!["let a = 1;", "let b = fn() { 4; };", "return a;"], "wrong statements after recovery");
+    }
+
+    #[test]
+    fn test_doc_comments_attach_to_let() {
+        let input = r#"
+            /// The answer.
+            let answer = 42;
+            let undocumented = 1;
+            /// Doubles x.
+            /// Works on integers.
+            let double = fn(x) {
+                /// inner helper
+                let two = 2;
+                x * two
+            };
+        "#;
+
+        let program = parse(input);
+        let docs = program.statements.iter()
+            .map(|stmt| match stmt {
+                ast::Statement::Let(ident, _, doc, _) => (ident.value.as_str(), doc.as_deref()),
+                _ => panic!("statement is not a let. got={}", stmt)
+            })
+            .collect::<Vec<_>>();
+
+        let expected = vec![
+            ("answer", Some("The answer.")),
+            ("undocumented", None),
+            ("double", Some("Doubles x.\nWorks on integers.")),
+        ];
+        assert_eq!(docs, expected, "wrong docs. got={:?}", docs);
+
+        match &program.statements[2] {
+            ast::Statement::Let(_, ast::Expression::FunctionLiteral { body, .. }, ..) => {
+                match &body.statements[0] {
+                    ast::Statement::Let(_, _, doc, _) => assert_eq!(doc.as_deref(), Some("inner helper"), "wrong inner doc"),
+                    stmt => panic!("statement is not a let. got={}", stmt)
+                }
+            },
+            stmt => panic!("statement is not a function binding. got={}", stmt)
+        }
+    }
 }
@@ -0,0 +1,269 @@
+use std::cell::RefCell;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+use crate::token::*;
+use crate::lexer::Lexer;
+use crate::parser::{parse, Parser};
+use crate::ast;
+use crate::cst;
+use crate::builtins::BUILTINS;
+use crate::evaluator::{self, Environment, Object, OverflowPolicy};
+use crate::diagnostic::Diagnostic;
+
+const PROMPT: &str = ">> ";
+const CONTINUATION_PROMPT: &str = ".. ";
+const FILE_NAME: &str = "<repl>";
+
+pub fn start<R: BufRead, W: Write>(mut input: R, mut output: W, color: bool, overflow: OverflowPolicy) -> io::Result<()> {
+    // what the program prints is collected and written out after each input, in order
+    // with the value it evaluates to
+    let printed = Rc::new(RefCell::new(Vec::new()));
+    let mut env = Environment::with_output(overflow, printed.clone());
+    let mut buffer = String::new();
+    // every input so far, kept to show errors against. each input's spans continue where the
+    // previous one ended, so a runtime error in a function defined by an earlier input is
+    // still shown with the line it came from
+    let mut session = String::new();
+    let mut line_count = 0;
+
+    loop {
+        let prompt = if buffer.is_empty() { PROMPT } else { CONTINUATION_PROMPT };
+        write!(output, "{}", prompt)?;
+        output.flush()?;
+
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            writeln!(output)?;
+            return Ok(());
+        }
+
+        if buffer.is_empty() {
+            let cmd = line.trim();
+            if cmd == ":quit" {
+                return Ok(());
+            } else if let Some(src) = command_arg(cmd, ":tokens") {
+                dump_tokens(src, &mut output)?;
+                continue;
+            } else if let Some(src) = command_arg(cmd, ":ast") {
+                dump_ast(src, &mut output, color)?;
+                continue;
+            } else if let Some(src) = command_arg(cmd, ":cst") {
+                write!(output, "{}", cst::parse(src.trim()).dump())?;
+                continue;
+            } else if cmd == ":builtins" {
+                for builtin in BUILTINS {
+                    writeln!(output, "{}", builtin.usage)?;
+                }
+                continue;
+            }
+        }
+
+        buffer.push_str(&line);
+        if open_delimiters(&buffer) > 0 {
+            continue;
+        }
+
+        let src = std::mem::take(&mut buffer);
+        if src.trim().is_empty() {
+            continue;
+        }
+
+        let start = session.len();
+        session.push_str(&src);
+
+        let mut result = vec![];
+        run(&session, start, line_count + 1, &mut env, &mut result, color)?;
+        line_count += src.matches('\n').count();
+        output.write_all(&std::mem::take(&mut *printed.borrow_mut()))?;
+        output.write_all(&result)?;
+    }
+}
+
+// runs the input that starts at start on the given line, diagnostics are shown against the whole session
+fn run<W: Write>(session: &str, start: usize, line: usize, env: &mut Environment, output: &mut W, color: bool) -> io::Result<()> {
+    let mut parser = Parser::new(Lexer::with_offset(&session[start..], start, line)).with_big_integers(env.overflow_policy() == OverflowPolicy::Promote);
+    let program = match parser.parse_program() {
+        Ok(program) => program,
+        Err(errors) => {
+            let diagnostics = errors.iter().map(Diagnostic::from_parse_error).collect::<Vec<_>>();
+            return print_diagnostics(&diagnostics, session, output, color);
+        }
+    };
+
+    let evaluated = evaluator::eval(&program, env);
+    if let Object::Error(kind, msg, span) = &evaluated {
+        return print_diagnostics(&[Diagnostic::runtime_error(*kind, msg, *span)], session, output, color);
+    }
+
+    // a trailing let doesn't produce a value worth echoing back
+    if let Some(ast::Statement::Let(..)) = program.statements.last() {
+        return Ok(());
+    }
+
+    writeln!(output, "{}", evaluated)
+}
+
+fn print_diagnostics<W: Write>(diagnostics: &[Diagnostic], src: &str, output: &mut W, color: bool) -> io::Result<()> {
+    for diagnostic in diagnostics {
+        write!(output, "{}", diagnostic.render(FILE_NAME, src, color))?;
+    }
+
+    Ok(())
+}
+
+// the source after a ':name' command, None if cmd is some other command
+fn command_arg<'a>(cmd: &'a str, name: &str) -> Option<&'a str> {
+    let rest = cmd.strip_prefix(name)?;
+    if rest.is_empty() || rest.starts_with(char::is_whitespace) {
+        Some(rest)
+    } else {
+        None
+    }
+}
+
+fn dump_tokens<W: Write>(src: &str, output: &mut W) -> io::Result<()> {
+    let mut l = Lexer::new(src);
+    loop {
+        let tok = l.next_token();
+        writeln!(output, "{:?} {:?}", tok.typ, tok.literal)?;
+
+        if tok.typ == TokenType::EOF {
+            return Ok(());
+        }
+    }
+}
+
+fn dump_ast<W: Write>(src: &str, output: &mut W, color: bool) -> io::Result<()> {
+    match parse(src) {
+        Ok(program) => writeln!(output, "{:#?}", program),
+        Err(errors) => {
+            let diagnostics = errors.iter().map(Diagnostic::from_parse_error).collect::<Vec<_>>();
+            print_diagnostics(&diagnostics, src, output, color)
+        }
+    }
+}
+
+// how many braces and parens are still waiting to be closed, used to keep reading lines
+fn open_delimiters(src: &str) -> i32 {
+    let mut l = Lexer::new(src);
+    let mut depth = 0;
+
+    loop {
+        let tok = l.next_token();
+        match tok.typ {
+            TokenType::Lbrace | TokenType::Lparen | TokenType::Lbracket => depth += 1,
+            TokenType::Rbrace | TokenType::Rparen | TokenType::Rbracket => depth -= 1,
+            // an open string or comment also needs another line to finish
+            TokenType::UnterminatedString | TokenType::UnterminatedComment => return depth + 1,
+            TokenType::EOF => return depth,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn run_session(input: &str) -> String {
+        let mut output = vec![];
+        if let Err(e) = start(input.as_bytes(), &mut output, false, OverflowPolicy::Checked) {
+            panic!("repl failed: {}", e);
+        }
+
+        String::from_utf8(output).unwrap_or_default()
+    }
+
+    #[test]
+    fn test_open_delimiters() {
+        let tests = [
+            ("let a = 5;", 0),
+            ("let add = fn(x, y) {", 1),
+            ("if (x) { fn(a) {", 2),
+            ("add(1, 2)", 0),
+            ("}", -1),
+            ("let s = \"{ (", 1),
+            ("let s = \"{ (\";", 0),
+            ("let s = 1; /* {", 1),
+            ("let s = 1; // {", 0),
+            ("let a = [1,", 1),
+            ("a[[0][0]]", 0),
+        ];
+
+        for (input, expected) in tests.iter() {
+            let depth = open_delimiters(input);
+            assert_eq!(depth, *expected, "wrong depth for '{}'. expected={}, got={}", input, expected, depth);
+        }
+    }
+
+    #[test]
+    fn test_bindings_persist_across_lines() {
+        let output = run_session("let a = 5;\nlet b = a * 2;\na + b\n:quit\n");
+        assert_eq!(output, ">> >> >> 15\n>> ", "wrong repl output. got={:?}", output);
+    }
+
+    #[test]
+    fn test_multiline_input() {
+        let output = run_session("let add = fn(x, y) {\n  x + y\n};\nadd(1, 2)\n");
+        assert_eq!(output, ">> .. .. >> 3\n>> \n", "wrong repl output. got={:?}", output);
+    }
+
+    #[test]
+    fn test_parser_errors() {
+        let output = run_session("let = 5;\n:quit\n");
+        assert!(output.contains("error[E0001]: expected next token to be 'Ident', got '=' instead\n --> <repl>:1:5\n"), "parser errors were not printed. got={:?}", output);
+    }
+
+    #[test]
+    fn test_runtime_errors() {
+        let output = run_session("let a = 1;\na + true\n:quit\n");
+        // lines count from the start of the session
+        assert!(output.contains("error[E1002]: type mismatch: INTEGER + BOOLEAN\n --> <repl>:2:1\n"), "runtime error was not printed. got={:?}", output);
+        assert!(output.contains("2 | a + true\n  | ^^^^^^^^\n"), "runtime error has no marker. got={:?}", output);
+    }
+
+    #[test]
+    fn test_runtime_errors_in_earlier_inputs() {
+        let output = run_session("let f = fn(a) {\n  a / 0\n};\nf(1)\n:quit\n");
+        assert!(output.contains("error[E1004]: division by zero: 1 / 0\n --> <repl>:2:3\n"), "wrong error location. got={:?}", output);
+        assert!(output.contains("2 |   a / 0\n  |   ^^^^^\n"), "error should point into the function body. got={:?}", output);
+    }
+
+    #[test]
+    fn test_commands() {
+        let output = run_session(":tokens let x\n:quit\n");
+        assert_eq!(output, ">> Let \"let\"\nIdent \"x\"\nEOF \"\"\n>> ", "wrong :tokens output. got={:?}", output);
+
+        let output = run_session(":ast 1 + 2\n:quit\n");
+        assert!(output.contains("Infix {"), "wrong :ast output. got={:?}", output);
+
+        let output = run_session(":cst x // hi\n:quit\n");
+        assert_eq!(output, ">> Program@0..7\n  ExpressionStatement@0..1\n    Name@0..1\n      Ident@0..1 \"x\"\n  Whitespace@1..2 \" \"\n  Comment@2..7 \"// hi\"\n>> ", "wrong :cst output. got={:?}", output);
+
+        // a command is a whole word, anything longer is evaluated as code
+        let output = run_session(":tokensfoo\n:quit\n");
+        assert!(output.contains("error[E0002]: no prefix parse function for ':' found"), "wrong output for ':tokensfoo'. got={:?}", output);
+
+        let output = run_session(":builtins\n:quit\n");
+        assert!(output.starts_with(">> puts(x, ...): prints each argument on its own line\nlen(x): "), "wrong :builtins output. got={:?}", output);
+        // one line per builtin, then the next prompt
+        assert_eq!(output.lines().count(), BUILTINS.len() + 1, "every builtin should be listed. got={:?}", output);
+    }
+
+    #[test]
+    fn test_puts_writes_to_the_repl_output() {
+        let output = run_session("puts(\"hi\", [1]); 2\nputs(1); foo\n:quit\n");
+        assert!(output.starts_with(">> hi\n[1]\n2\n>> 1\nerror[E1001]: identifier not found: foo\n"), "wrong repl output. got={:?}", output);
+    }
+
+    #[test]
+    fn test_big_literals_with_promote() {
+        let mut output = vec![];
+        if let Err(e) = start("9223372036854775808 * 2\n".as_bytes(), &mut output, false, OverflowPolicy::Promote) {
+            panic!("repl failed: {}", e);
+        }
+
+        let output = String::from_utf8(output).unwrap_or_default();
+        assert_eq!(output, ">> 18446744073709551616\n>> \n", "wrong repl output. got={:?}", output);
+    }
+}
@@ -1,35 +1,82 @@
 use std::fmt;
 
+// byte range [start, end) in the source, plus the 1-based line and column where it starts
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub col: usize
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, line: usize, col: usize) -> Span {
+        Span {
+            start,
+            end,
+            line,
+            col
+        }
+    }
+
+    // from the start of this span to the end of the other one
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start, other.end, self.line, self.col)
+    }
+}
+
+impl std::fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Token {
     pub typ: TokenType,
-    pub literal: String
+    pub literal: String,
+    pub span: Span,
+    // '///' doc comment lines found right before this token
+    pub doc: Option<String>
 }
 
 impl Token {
     pub fn new(typ: TokenType, literal: &str) -> Token {
         Token {
-            typ: typ,
-            literal: literal.to_string()
+            typ,
+            literal: literal.to_string(),
+            span: Span::default(),
+            doc: None
         }
     }
 
     pub fn from_char(typ: TokenType, ch: char) -> Token {
         Token::new(typ, &ch.to_string())
     }
+}
 
-    pub fn default() -> Token {
+impl Default for Token {
+    fn default() -> Token {
         Token::new(TokenType::Illegal, "")
     }
 }
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(PartialEq, Debug, Clone)]
 pub enum TokenType {
     Illegal,
     EOF,
+    UnterminatedString,
+    InvalidEscape,
+    UnterminatedComment,
+
+    // trivia, only produced by a lexer in lossless mode
+    Whitespace,
+    Comment,
 
     Ident,
     Int,
+    Float,
     String,
 
     Assign,
@@ -46,11 +93,14 @@ pub enum TokenType {
 
     Comma,
     Semicolon,
+    Colon,
 
     Lparen,
     Rparen,
     Lbrace,
     Rbrace,
+    Lbracket,
+    Rbracket,
 
     Function,
     Let,
@@ -61,13 +111,52 @@ pub enum TokenType {
     Return,
 }
 
+impl TokenType {
+    pub fn is_trivia(&self) -> bool {
+        matches!(self, TokenType::Whitespace | TokenType::Comment)
+    }
+}
+
 impl std::fmt::Display for TokenType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let val = match self {
-            _Illegal => "Illegal",
-            _EOF => "EOF",
-            _Ident => "Ident",
-            _ => "Unimplemented!"
+            TokenType::Illegal => "Illegal",
+            TokenType::EOF => "EOF",
+            TokenType::UnterminatedString => "UnterminatedString",
+            TokenType::InvalidEscape => "InvalidEscape",
+            TokenType::UnterminatedComment => "UnterminatedComment",
+            TokenType::Whitespace => "Whitespace",
+            TokenType::Comment => "Comment",
+            TokenType::Ident => "Ident",
+            TokenType::Int => "Int",
+            TokenType::Float => "Float",
+            TokenType::String => "String",
+            TokenType::Assign => "=",
+            TokenType::Plus => "+",
+            TokenType::Minus => "-",
+            TokenType::Bang => "!",
+            TokenType::Asterisk => "*",
+            TokenType::Slash => "/",
+            TokenType::LT => "<",
+            TokenType::GT => ">",
+            TokenType::Equal => "==",
+            TokenType::NotEqual => "!=",
+            TokenType::Comma => ",",
+            TokenType::Semicolon => ";",
+            TokenType::Colon => ":",
+            TokenType::Lparen => "(",
+            TokenType::Rparen => ")",
+            TokenType::Lbrace => "{",
+            TokenType::Rbrace => "}",
+            TokenType::Lbracket => "[",
+            TokenType::Rbracket => "]",
+            TokenType::Function => "fn",
+            TokenType::Let => "let",
+            TokenType::True => "true",
+            TokenType::False => "false",
+            TokenType::If => "if",
+            TokenType::Else => "else",
+            TokenType::Return => "return",
         };
 
         write!(f, "{}", val)